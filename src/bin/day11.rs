@@ -1,8 +1,54 @@
 use std::env;
 use std::error::Error;
-use std::io::{self, Read};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
 use itertools::Itertools;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Neighbours {
+    Adjacent,
+    /// The first seat in each of the eight directions, looking past floor.
+    LineOfSight,
+}
+
+struct Rules {
+    neighbours: Neighbours,
+    /// An occupied seat is left once this many of its neighbours are taken.
+    tolerance: usize,
+    max_rounds: Option<usize>,
+}
+
+const PART1: Rules = Rules {
+    neighbours: Neighbours::Adjacent,
+    tolerance: 4,
+    max_rounds: None,
+};
+
+const PART2: Rules = Rules {
+    neighbours: Neighbours::LineOfSight,
+    tolerance: 5,
+    max_rounds: None,
+};
+
+/// The waiting area as one row-major vector, with every seat's neighbours
+/// worked out up front since the floor never changes.
+struct Seating {
+    cols: usize,
+    cells: Vec<char>,
+    neighbours: Vec<Vec<usize>>,
+    tolerance: usize,
+}
+
+struct Outcome {
+    occupied: usize,
+    rounds: usize,
+    /// Whether it stopped because nothing changed rather than hitting
+    /// `max_rounds`.
+    settled: bool,
+}
+
 fn parse(buffer: &str) -> Vec<Vec<char>> {
     buffer
         .lines()
@@ -10,89 +56,198 @@ fn parse(buffer: &str) -> Vec<Vec<char>> {
         .collect::<Vec<_>>()
 }
 
-fn simulate<F>(buffer: &str, f: F) -> usize
-where
-    F: Fn(&Vec<Vec<char>>, usize, usize) -> char,
-{
-    let mut grid = parse(buffer);
-    let mut next_grid = grid.clone();
+fn get(grid: &[Vec<char>], row: i32, col: i32) -> Option<char> {
+    if row < 0 || col < 0 {
+        None
+    } else {
+        grid.get(row as usize)
+            .and_then(|row| row.get(col as usize))
+            .copied()
+    }
+}
 
-    loop {
-        let mut changed = false;
+impl Seating {
+    fn new(buffer: &str, rules: &Rules) -> Result<Seating, Box<dyn Error>> {
+        let grid = parse(buffer);
+        let cols = grid.first().map_or(0, |x| x.len());
+        if grid.iter().any(|x| x.len() != cols) {
+            return Err("Rows have different lengths".into());
+        }
+
+        let reach = match rules.neighbours {
+            Neighbours::Adjacent => 1,
+            Neighbours::LineOfSight => usize::MAX,
+        };
 
-        for row in grid.iter().enumerate() {
-            for col in row.1.iter().enumerate() {
-                let ch = f(&grid, row.0, col.0);
-                changed |= ch != *col.1;
-                next_grid[row.0][col.0] = ch;
+        let mut neighbours = vec![];
+        for (row, line) in grid.iter().enumerate() {
+            for (col, ch) in line.iter().enumerate() {
+                if *ch == '.' {
+                    neighbours.push(vec![]);
+                    continue;
+                }
+
+                let seen = (-1..=1)
+                    .cartesian_product(-1..=1)
+                    .filter(|(dr, dc)| *dr != 0 || *dc != 0)
+                    .filter_map(|(dr, dc)| {
+                        let (mut r, mut c) = (row as i32, col as i32);
+                        for _ in 0..reach {
+                            r += dr;
+                            c += dc;
+                            match get(&grid, r, c) {
+                                Some('.') => {}
+                                Some(_) => return Some(r as usize * cols + c as usize),
+                                None => return None,
+                            }
+                        }
+                        None
+                    })
+                    .collect();
+                neighbours.push(seen);
             }
         }
 
-        let temp = grid;
-        grid = next_grid;
-        next_grid = temp;
+        Ok(Seating {
+            cols,
+            cells: grid.into_iter().flatten().collect(),
+            neighbours,
+            tolerance: rules.tolerance,
+        })
+    }
+
+    /// Moves everyone at once, returning how many seats changed.
+    fn step(&mut self) -> usize {
+        let next = self
+            .cells
+            .iter()
+            .zip(&self.neighbours)
+            .map(|(ch, seen)| {
+                let occ = seen.iter().filter(|x| self.cells[**x] == '#').count();
+                match ch {
+                    'L' if occ == 0 => '#',
+                    '#' if occ >= self.tolerance => 'L',
+                    x => *x,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let changed = self.cells.iter().zip(&next).filter(|(a, b)| a != b).count();
+        self.cells = next;
+        changed
+    }
+
+    fn occupied(&self) -> usize {
+        self.cells.iter().filter(|x| **x == '#').count()
+    }
 
-        if !changed {
-            return grid.iter().flatten().filter(|x| **x == '#').count();
+    /// The grid as in the puzzle, with occupied seats in red and empty ones
+    /// in green if `colour` is set.
+    fn render(&self, colour: bool) -> String {
+        let mut out = String::new();
+        for row in self.cells.chunks(self.cols.max(1)) {
+            for ch in row {
+                match (colour, ch) {
+                    (true, '#') => out.push_str("\x1b[31m#\x1b[0m"),
+                    (true, 'L') => out.push_str("\x1b[32mL\x1b[0m"),
+                    (_, x) => out.push(*x),
+                }
+            }
+            out.push('\n');
         }
+        out
     }
 }
 
-fn get(grid: &Vec<Vec<char>>, row: i32, col: i32) -> Option<char> {
-    if row < 0 || col < 0 {
-        return None;
-    } else {
-        grid.get(row as usize).and_then(|row| row.get(col as usize)).copied()
+/// Runs the simulation, handing `frame` the round number, the seating and
+/// how many seats that round changed: first the starting layout, then
+/// after every round.
+fn replay<F>(buffer: &str, rules: &Rules, mut frame: F) -> Result<Outcome, Box<dyn Error>>
+where
+    F: FnMut(usize, &Seating, usize) -> Result<(), Box<dyn Error>>,
+{
+    let mut seating = Seating::new(buffer, rules)?;
+    let mut rounds = 0;
+    frame(0, &seating, 0)?;
+
+    loop {
+        if rules.max_rounds.is_some_and(|x| rounds >= x) {
+            return Ok(Outcome {
+                occupied: seating.occupied(),
+                rounds,
+                settled: false,
+            });
+        }
+
+        rounds += 1;
+        let changed = seating.step();
+        frame(rounds, &seating, changed)?;
+        if changed == 0 {
+            return Ok(Outcome {
+                occupied: seating.occupied(),
+                rounds,
+                settled: true,
+            });
+        }
     }
 }
 
-fn solve1(buffer: &str) -> usize {
-    simulate(buffer, |grid, row, col| {
-        let occ = (-1..=1).cartesian_product(-1..=1)
-            .filter(|(dr, dc)| *dr != 0 || *dc != 0)
-            .filter(|(dr, dc)| match get(grid, row as i32 + dr, col as i32 + dc) {
-                Some('#') => true,
-                _ => false,
-            })
-            .count();
+fn simulate(buffer: &str, rules: &Rules) -> Result<Outcome, Box<dyn Error>> {
+    replay(buffer, rules, |_, _, _| Ok(()))
+}
 
-        match grid[row][col] {
-            'L' if occ == 0 => '#',
-            '#' if occ >= 4 => 'L',
-            x => x,
-        }
-    })
-}
-
-fn solve2(buffer: &str) -> usize {
-    simulate(buffer, |grid, row, col| {
-        let occ = (-1..=1).cartesian_product(-1..=1)
-            .filter(|(dr, dc)| *dr != 0 || *dc != 0)
-            .filter(|(dr, dc)| {
-                let mut r: i32 = row as i32;
-                let mut c: i32 = col as i32;
-
-                loop {
-                    r += dr;
-                    c += dc;
-
-                    match get(grid, r, c) {
-                        Some('#') => return true,
-                        Some('L') | None => break,
-                        _ => {}
-                    }
-                }
+fn caption(round: usize, seating: &Seating, changed: usize) -> String {
+    format!(
+        "Round {}: {} seats changed, {} occupied\n",
+        round,
+        changed,
+        seating.occupied()
+    )
+}
 
-                false
-            })
-            .count();
+/// Whether `replay` stops after this frame: nothing changed, or the round
+/// limit was reached.
+fn last_frame(rules: &Rules, round: usize, changed: usize) -> bool {
+    (round > 0 && changed == 0) || rules.max_rounds == Some(round)
+}
 
-        match grid[row][col] {
-            'L' if occ == 0 => '#',
-            '#' if occ >= 5 => 'L',
-            x => x,
+/// Redraws the terminal for every round, pausing `delay` between them.
+fn animate(buffer: &str, rules: &Rules, delay: std::time::Duration) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    replay(buffer, rules, |round, seating, changed| {
+        write!(out, "\x1b[2J\x1b[H{}", caption(round, seating, changed))?;
+        write!(out, "{}", seating.render(true))?;
+        out.flush()?;
+        if !last_frame(rules, round, changed) {
+            std::thread::sleep(delay);
         }
-    })
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Writes every round to `dir` as frame_0000.txt, frame_0001.txt and so on.
+/// `dir` must be new or empty, so no frames from an earlier run get mixed in.
+fn write_frames(buffer: &str, rules: &Rules, dir: &Path) -> Result<usize, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    if fs::read_dir(dir)?.next().is_some() {
+        return Err(format!("{} is not empty", dir.display()).into());
+    }
+    let outcome = replay(buffer, rules, |round, seating, changed| {
+        let frame = caption(round, seating, changed) + &seating.render(false);
+        fs::write(dir.join(format!("frame_{:04}.txt", round)), frame)?;
+        Ok(())
+    })?;
+    Ok(outcome.rounds + 1)
+}
+
+fn solve1(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    Ok(simulate(buffer, &PART1)?.occupied)
+}
+
+fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    Ok(simulate(buffer, &PART2)?.occupied)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -102,10 +257,47 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "2" {
-        println!("{}", solve2(&buffer));
+    let part = |arg: Option<&String>| match arg.map(|x| x.as_str()) {
+        Some("1") => Ok(PART1),
+        Some("2") => Ok(PART2),
+        _ => Err("Expected part 1 or 2"),
+    };
+
+    if args.len() > 1 && args[1] == "animate" {
+        // animate <1|2> [delay ms]
+        let delay = args.get(3).map_or(Ok(200), |x| x.parse::<u64>())?;
+        let rules = part(args.get(2))?;
+        animate(&buffer, &rules, std::time::Duration::from_millis(delay))?;
+    } else if args.len() > 3 && args[1] == "frames" {
+        // frames <1|2> <new or empty dir>
+        let count = write_frames(&buffer, &part(args.get(2))?, Path::new(&args[3]))?;
+        println!("Wrote {} frames to {}", count, args[3]);
+    } else if args.len() > 3 && args[1] == "rules" {
+        // rules <adjacent|sight> <tolerance> [max rounds]
+        let rules = Rules {
+            neighbours: match args[2].as_str() {
+                "adjacent" => Neighbours::Adjacent,
+                "sight" => Neighbours::LineOfSight,
+                x => return Err(format!("Unknown neighbours '{}'", x).into()),
+            },
+            tolerance: args[3].parse::<usize>()?,
+            max_rounds: args.get(4).map(|x| x.parse::<usize>()).transpose()?,
+        };
+        let outcome = simulate(&buffer, &rules)?;
+        println!(
+            "{} occupied after {} rounds{}",
+            outcome.occupied,
+            outcome.rounds,
+            if outcome.settled {
+                ""
+            } else {
+                " (still changing)"
+            }
+        );
+    } else if args.len() > 1 && args[1] == "2" {
+        println!("{}", solve2(&buffer)?);
     } else {
-        println!("{}", solve1(&buffer));
+        println!("{}", solve1(&buffer)?);
     }
 
     eprintln!("Time: {}ms", now.elapsed().as_millis());
@@ -116,7 +308,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod test {
     use super::*;
 
-    const INPUT: &'static str = "L.LL.LL.LL
+    const INPUT: &str = "L.LL.LL.LL
 LLLLLLL.LL
 L.L.L..L..
 LLLL.LL.LL
@@ -129,11 +321,80 @@ L.LLLLL.LL";
 
     #[test]
     fn test1() {
-        assert_eq!(solve1(INPUT), 37);
+        assert_eq!(solve1(INPUT).unwrap(), 37);
     }
 
     #[test]
     fn test2() {
-        assert_eq!(solve2(INPUT), 26);
+        assert_eq!(solve2(INPUT).unwrap(), 26);
+    }
+
+    #[test]
+    fn test_rules() {
+        let seating = Seating::new(".L.L\n....\n.L.L", &PART2).unwrap();
+        assert_eq!(seating.neighbours[1], vec![3, 9, 11]);
+        assert!(seating.neighbours[0].is_empty());
+        let seating = Seating::new(".L.L\n....\n.L.L", &PART1).unwrap();
+        assert!(seating.neighbours[1].is_empty());
+
+        // Everyone sits down in the first round and half leave in the second
+        let rules = Rules {
+            max_rounds: Some(2),
+            ..PART1
+        };
+        let outcome = simulate(INPUT, &rules).unwrap();
+        assert_eq!(
+            (outcome.occupied, outcome.rounds, outcome.settled),
+            (20, 2, false)
+        );
+
+        let outcome = simulate(INPUT, &PART1).unwrap();
+        assert_eq!((outcome.rounds, outcome.settled), (6, true));
+
+        // Nobody minds company, so nobody ever gets up again
+        let rules = Rules {
+            tolerance: 9,
+            ..PART1
+        };
+        assert_eq!(simulate(INPUT, &rules).unwrap().occupied, 71);
+
+        assert!(Seating::new("L.L\nLL", &PART1).is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut log = vec![];
+        replay(INPUT, &PART1, |round, seating, changed| {
+            log.push((round, changed, seating.occupied()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(log[..3], [(0, 0, 0), (1, 71, 71), (2, 51, 20)]);
+        assert_eq!(log.last(), Some(&(6, 0, 37)));
+
+        let seating = Seating::new("#.L\nL.#", &PART1).unwrap();
+        assert_eq!(seating.render(false), "#.L\nL.#\n");
+        assert_eq!(seating.render(true).matches("\x1b[31m#").count(), 2);
+
+        let dir = env::temp_dir().join(format!("day11-frames-{}", std::process::id()));
+        assert_eq!(write_frames(INPUT, &PART2, &dir).unwrap(), 8);
+        let first = fs::read_to_string(dir.join("frame_0001.txt")).unwrap();
+        assert!(first.starts_with("Round 1: 71 seats changed, 71 occupied\n#.##.##.##\n"));
+
+        // A second run would leave stale frames behind if it had fewer rounds
+        let err = write_frames(INPUT, &PART1, &dir).unwrap_err().to_string();
+        assert!(err.ends_with("is not empty"), "{}", err);
+        assert!(dir.join("frame_0007.txt").exists());
+        fs::remove_dir_all(&dir).unwrap();
+
+        // No pause after the frame where the seating settles or runs out
+        assert!(!last_frame(&PART2, 0, 0));
+        assert!(!last_frame(&PART2, 3, 5));
+        assert!(last_frame(&PART2, 7, 0));
+        let limited = Rules {
+            max_rounds: Some(2),
+            ..PART1
+        };
+        assert!(last_frame(&limited, 2, 5));
     }
 }
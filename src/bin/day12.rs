@@ -1,89 +1,226 @@
 use std::env;
 use std::error::Error;
+use std::fmt;
 use std::io::{self, Read};
+use std::str::FromStr;
 
-fn solve1(buffer: &str) -> Result<i32, Box<dyn Error>> {
-    let mut dir: (i32, i32) = (1, 0);
-    let mut pos: (i32, i32) = (0, 0);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Instruction {
+    North(i32),
+    South(i32),
+    East(i32),
+    West(i32),
+    /// Quarter turns anticlockwise.
+    Left(i32),
+    /// Quarter turns clockwise.
+    Right(i32),
+    Forward(i32),
+}
 
-    for line in buffer.lines() {
-        let inst = &line[0..1];
-        let dist = &line[1..].parse::<i32>()?;
+impl FromStr for Instruction {
+    type Err = Box<dyn Error>;
 
-        match inst {
-            "N" => {
-                pos.1 += dist;
-            }
-            "E" => {
-                pos.0 += dist;
-            }
-            "W" => {
-                pos.0 -= dist;
-            }
-            "S" => {
-                pos.1 -= dist;
-            }
-            "L" => {
-                for _ in 0..(dist / 90) {
-                    dir = (-dir.1, dir.0)
-                }
-            }
-            "R" => {
-                for _ in 0..(dist / 90) {
-                    dir = (dir.1, -dir.0)
-                }
-            }
-            "F" => {
-                pos.0 += dir.0 * dist;
-                pos.1 += dir.1 * dist;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let action = s.chars().next().ok_or("Empty instruction")?;
+        let value = s[action.len_utf8()..].parse::<i32>()?;
+
+        let turns = || {
+            if value % 90 == 0 {
+                Ok(value / 90)
+            } else {
+                Err(format!(
+                    "Can only turn by multiples of 90 degrees, not '{}'",
+                    s
+                ))
             }
-            _ => unreachable!(),
+        };
+
+        Ok(match action {
+            'N' => Instruction::North(value),
+            'S' => Instruction::South(value),
+            'E' => Instruction::East(value),
+            'W' => Instruction::West(value),
+            'L' => Instruction::Left(turns()?),
+            'R' => Instruction::Right(turns()?),
+            'F' => Instruction::Forward(value),
+            _ => return Err(format!("Unknown instruction '{}'", s).into()),
+        })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::North(x) => write!(f, "N{}", x),
+            Instruction::South(x) => write!(f, "S{}", x),
+            Instruction::East(x) => write!(f, "E{}", x),
+            Instruction::West(x) => write!(f, "W{}", x),
+            Instruction::Left(x) => write!(f, "L{}", x * 90),
+            Instruction::Right(x) => write!(f, "R{}", x * 90),
+            Instruction::Forward(x) => write!(f, "F{}", x),
         }
     }
+}
 
-    Ok(pos.0.abs() + pos.1.abs())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// N, S, E and W move the ship; F follows its heading.
+    Heading,
+    /// N, S, E and W move the waypoint; F heads for it, as many times over.
+    Waypoint,
 }
 
-fn solve2(buffer: &str) -> Result<i32, Box<dyn Error>> {
-    let mut waypoint: (i32, i32) = (10, 1);
-    let mut pos: (i32, i32) = (0, 0);
+/// Where the ship is and which way `vector` points after an instruction:
+/// the heading or the waypoint, relative to the ship.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Record {
+    pos: (i32, i32),
+    vector: (i32, i32),
+}
 
-    for line in buffer.lines() {
-        let inst = &line[0..1];
-        let dist = &line[1..].parse::<i32>()?;
+struct Ship {
+    mode: Mode,
+    pos: (i32, i32),
+    vector: (i32, i32),
+    /// The start and the state after every instruction since.
+    path: Vec<Record>,
+}
 
-        match inst {
-            "N" => {
-                waypoint.1 += dist;
-            }
-            "E" => {
-                waypoint.0 += dist;
-            }
-            "W" => {
-                waypoint.0 -= dist;
-            }
-            "S" => {
-                waypoint.1 -= dist;
-            }
-            "L" => {
-                for _ in 0..(dist / 90) {
-                    waypoint = (-waypoint.1, waypoint.0);
-                }
-            }
-            "R" => {
-                for _ in 0..(dist / 90) {
-                    waypoint = (waypoint.1, -waypoint.0);
-                }
-            }
-            "F" => {
-                pos.0 += waypoint.0 * dist;
-                pos.1 += waypoint.1 * dist;
-            }
-            _ => unreachable!(),
+impl Ship {
+    fn new(mode: Mode) -> Ship {
+        let vector = match mode {
+            Mode::Heading => (1, 0),
+            Mode::Waypoint => (10, 1),
+        };
+        Ship {
+            mode,
+            pos: (0, 0),
+            vector,
+            path: vec![Record {
+                pos: (0, 0),
+                vector,
+            }],
         }
     }
 
-    Ok(pos.0.abs() + pos.1.abs())
+    fn apply(&mut self, inst: Instruction) {
+        let shift = |(dx, dy): (i32, i32)| match self.mode {
+            Mode::Heading => (self.pos.0 + dx, self.pos.1 + dy, self.vector),
+            Mode::Waypoint => (
+                self.pos.0,
+                self.pos.1,
+                (self.vector.0 + dx, self.vector.1 + dy),
+            ),
+        };
+        let turn = |turns: i32| (0..turns.rem_euclid(4)).fold(self.vector, |(x, y), _| (-y, x));
+
+        let (x, y, vector) = match inst {
+            Instruction::North(n) => shift((0, n)),
+            Instruction::South(n) => shift((0, -n)),
+            Instruction::East(n) => shift((n, 0)),
+            Instruction::West(n) => shift((-n, 0)),
+            Instruction::Left(turns) => (self.pos.0, self.pos.1, turn(turns)),
+            Instruction::Right(turns) => (self.pos.0, self.pos.1, turn(-turns)),
+            Instruction::Forward(n) => (
+                self.pos.0 + self.vector.0 * n,
+                self.pos.1 + self.vector.1 * n,
+                self.vector,
+            ),
+        };
+
+        self.pos = (x, y);
+        self.vector = vector;
+        self.path.push(Record {
+            pos: self.pos,
+            vector,
+        });
+    }
+
+    fn distance(&self) -> i32 {
+        self.pos.0.abs() + self.pos.1.abs()
+    }
+
+    /// The furthest the ship got from the start, by Manhattan distance.
+    fn max_distance(&self) -> i32 {
+        self.path
+            .iter()
+            .map(|x| x.pos.0.abs() + x.pos.1.abs())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The lowest and highest x and y the ship visited.
+    fn bounding_box(&self) -> ((i32, i32), (i32, i32)) {
+        self.path.iter().fold(((0, 0), (0, 0)), |(min, max), x| {
+            (
+                (min.0.min(x.pos.0), min.1.min(x.pos.1)),
+                (max.0.max(x.pos.0), max.1.max(x.pos.1)),
+            )
+        })
+    }
+
+    /// The Manhattan length of every leg added up.
+    fn travelled(&self) -> i32 {
+        self.path
+            .windows(2)
+            .map(|w| (w[1].pos.0 - w[0].pos.0).abs() + (w[1].pos.1 - w[0].pos.1).abs())
+            .sum()
+    }
+}
+
+fn parse(buffer: &str) -> Result<Vec<Instruction>, Box<dyn Error>> {
+    buffer
+        .lines()
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| x.parse::<Instruction>())
+        .collect()
+}
+
+fn navigate(buffer: &str, mode: Mode) -> Result<Ship, Box<dyn Error>> {
+    let mut ship = Ship::new(mode);
+    for inst in parse(buffer)? {
+        ship.apply(inst);
+    }
+    Ok(ship)
+}
+
+fn show_path(buffer: &str, mode: Mode) -> Result<String, Box<dyn Error>> {
+    let ship = navigate(buffer, mode)?;
+    let mut out = String::new();
+    for (inst, record) in parse(buffer)?.iter().zip(&ship.path[1..]) {
+        out.push_str(&format!(
+            "{:<5} position {:?} {} {:?}\n",
+            inst.to_string(),
+            record.pos,
+            match mode {
+                Mode::Heading => "heading",
+                Mode::Waypoint => "waypoint",
+            },
+            record.vector
+        ));
+    }
+
+    let (min, max) = ship.bounding_box();
+    out.push_str(&format!(
+        "Distance {}, furthest {}, travelled {}, x {}..{}, y {}..{}\n",
+        ship.distance(),
+        ship.max_distance(),
+        ship.travelled(),
+        min.0,
+        max.0,
+        min.1,
+        max.1
+    ));
+    Ok(out)
+}
+
+fn solve1(buffer: &str) -> Result<i32, Box<dyn Error>> {
+    Ok(navigate(buffer, Mode::Heading)?.distance())
+}
+
+fn solve2(buffer: &str) -> Result<i32, Box<dyn Error>> {
+    Ok(navigate(buffer, Mode::Waypoint)?.distance())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -93,7 +230,14 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "2" {
+    if args.len() > 1 && args[1] == "path" {
+        let mode = if args.len() > 2 && args[2] == "2" {
+            Mode::Waypoint
+        } else {
+            Mode::Heading
+        };
+        print!("{}", show_path(&buffer, mode)?);
+    } else if args.len() > 1 && args[1] == "2" {
         println!("{}", solve2(&buffer)?);
     } else {
         println!("{}", solve1(&buffer)?);
@@ -107,7 +251,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod test {
     use super::*;
 
-    const INPUT: &'static str = "F10
+    const INPUT: &str = "F10
 N3
 F7
 R90
@@ -117,4 +261,54 @@ F11";
     fn test1() {
         assert_eq!(solve1(INPUT).unwrap(), 25);
     }
+
+    #[test]
+    fn test2() {
+        assert_eq!(solve2(INPUT).unwrap(), 286);
+    }
+
+    #[test]
+    fn test_instructions() {
+        assert_eq!(
+            "R270".parse::<Instruction>().unwrap(),
+            Instruction::Right(3)
+        );
+        assert_eq!("F7".parse::<Instruction>().unwrap().to_string(), "F7");
+        assert!("R45".parse::<Instruction>().is_err());
+        assert!("X1".parse::<Instruction>().is_err());
+        assert!("N".parse::<Instruction>().is_err());
+
+        let mut ship = Ship::new(Mode::Heading);
+        ship.apply(Instruction::Left(5));
+        assert_eq!(ship.vector, (0, 1));
+        ship.apply(Instruction::Right(-1));
+        assert_eq!(ship.vector, (-1, 0));
+    }
+
+    #[test]
+    fn test_path() {
+        let ship = navigate(INPUT, Mode::Heading).unwrap();
+        assert_eq!(ship.path.len(), 6);
+        assert_eq!(
+            ship.path[4],
+            Record {
+                pos: (17, 3),
+                vector: (0, -1)
+            }
+        );
+        assert_eq!(ship.bounding_box(), ((0, -8), (17, 3)));
+        assert_eq!(ship.max_distance(), 25);
+        assert_eq!(ship.travelled(), 31);
+
+        let ship = navigate(INPUT, Mode::Waypoint).unwrap();
+        assert_eq!(ship.max_distance(), 286);
+        assert_eq!(ship.bounding_box(), ((0, -72), (214, 38)));
+        assert_eq!(ship.travelled(), 110 + 98 + 154);
+
+        let output = show_path(INPUT, Mode::Waypoint).unwrap();
+        assert!(output.starts_with("F10   position (100, 10) waypoint (10, 1)\n"));
+        assert!(
+            output.ends_with("Distance 286, furthest 286, travelled 362, x 0..214, y -72..38\n")
+        );
+    }
 }
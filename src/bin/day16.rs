@@ -1,130 +1,401 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::error::Error;
 use std::io::{self, Read};
 
+use aoc2020::matching::{Assignment, Solutions};
+use aoc2020::rng::Rng;
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
 
-fn parse(line: &str) -> Result<(String, Vec<(usize, usize)>), Box<dyn Error>> {
+type Rule = (String, Vec<(usize, usize)>);
+
+struct Notes {
+    rules: Vec<Rule>,
+    mine: Vec<usize>,
+    nearby: Vec<Vec<usize>>,
+}
+
+fn parse(line: &str) -> Result<Rule, Box<dyn Error>> {
     lazy_static! {
         static ref RE: Regex =
-            Regex::new(r#"(?P<label>.*?): (?P<a>\d+)-(?P<b>\d+) or (?P<c>\d+)-(?P<d>\d+).*"#)
-                .unwrap();
+            Regex::new(r#"^(?P<label>[^:]+): (?P<ranges>\d+-\d+( or \d+-\d+)*)$"#).unwrap();
+        static ref RANGE: Regex = Regex::new(r#"(?P<a>\d+)-(?P<b>\d+)"#).unwrap();
     }
 
-    fn val(c: Option<regex::Match>) -> Result<usize, Box<dyn Error>> {
-        Ok(c.unwrap().as_str().parse::<usize>()?)
+    let captures = RE
+        .captures(line.trim())
+        .ok_or_else(|| format!("Can't parse rule '{}'", line))?;
+
+    let mut ranges = vec![];
+    for range in RANGE.captures_iter(&captures["ranges"]) {
+        let (a, b) = (range["a"].parse::<usize>()?, range["b"].parse::<usize>()?);
+        if a > b {
+            return Err(format!("Range {}-{} in rule '{}' is backwards", a, b, line.trim()).into());
+        }
+        ranges.push((a, b));
     }
 
-    let captures = RE.captures(line).unwrap();
-    let result = vec![
-        (val(captures.name("a"))?, val(captures.name("b"))?),
-        (val(captures.name("c"))?, val(captures.name("d"))?),
-    ];
+    Ok((captures["label"].to_string(), ranges))
+}
 
-    Ok((captures.name("label").unwrap().as_str().to_string(), result))
+fn parse_ticket(line: &str) -> Result<Vec<usize>, Box<dyn Error>> {
+    Ok(line
+        .trim()
+        .split(',')
+        .map(|x| x.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()?)
 }
 
-fn solve1(buffer: &str) -> Result<usize, Box<dyn Error>> {
-    let pieces = buffer.split("\n\n").collect::<Vec<_>>();
+fn parse_notes(buffer: &str) -> Result<Notes, Box<dyn Error>> {
+    let pieces = buffer.trim().split("\n\n").collect::<Vec<_>>();
+    if pieces.len() != 3 {
+        return Err("Expected rules, your ticket and nearby tickets".into());
+    }
+
     let rules = pieces[0]
         .lines()
-        .filter_map(|line| parse(line).map(|x| x.1).ok())
-        .collect::<Vec<_>>();
-
-    Ok(pieces[2]
+        .map(parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mine = parse_ticket(pieces[1].lines().nth(1).ok_or("Missing your ticket")?)?;
+    let nearby = pieces[2]
         .lines()
         .skip(1)
-       .map(|line| line.split(","))
-       .flatten()
-       .filter_map(|var| var.parse::<usize>().ok())
-                .filter(|var| {
-                    !rules
-                        .iter()
-                        .any(|rule| rule.iter().any(|range| range.0 <= *var && *var <= range.1))
-                })
-        .sum())
+        .map(parse_ticket)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Notes {
+        rules,
+        mine,
+        nearby,
+    })
 }
 
-fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
-    let pieces = buffer.split("\n\n").collect::<Vec<_>>();
+fn allows(rule: &Rule, var: usize) -> bool {
+    rule.1.iter().any(|range| range.0 <= var && var <= range.1)
+}
 
-    let mut rules: Vec<(String, Vec<(usize, usize)>)> = vec![];
-    let mut positions: HashMap<String, HashSet<usize>> = HashMap::new();
+/// Checks every range of every rule; the baseline `bench` compares against.
+fn linear_invalid(rules: &[Rule], ticket: &[usize]) -> Vec<usize> {
+    ticket
+        .iter()
+        .copied()
+        .filter(|var| !rules.iter().any(|rule| allows(rule, *var)))
+        .collect()
+}
 
-    let myticket = pieces[1]
-        .lines()
-        .skip(1)
-        .next()
-        .unwrap()
-        .trim()
-        .split(",")
-        .map(|x| x.parse::<usize>().unwrap())
-        .collect::<Vec<usize>>();
-
-    for line in pieces[0].lines() {
-        if let Ok(r) = parse(line) {
-            positions.insert(r.0.clone(), (0..myticket.len()).collect::<HashSet<usize>>());
-            rules.push(r);
+fn linear_candidates(notes: &Notes) -> Vec<u64> {
+    let mut candidates = vec![all_fields(notes.rules.len()); notes.mine.len()];
+    let valid = notes
+        .nearby
+        .iter()
+        .filter(|ticket| linear_invalid(&notes.rules, ticket).is_empty());
+    for ticket in valid {
+        for (i, var) in ticket.iter().enumerate() {
+            for (f, rule) in notes.rules.iter().enumerate() {
+                if !allows(rule, *var) {
+                    candidates[i] &= !(1 << f);
+                }
+            }
         }
     }
+    candidates
+}
 
-    for line in pieces[2].lines().skip(1) {
-        for (i, var) in line.trim().split(",").enumerate() {
-            let var = var.parse::<usize>()?;
-            let mut passed = false;
-            'rules: for rule in &rules {
-                for range in &rule.1 {
-                    if var <= range.1 && var >= range.0 {
-                        passed = true;
-                        break 'rules;
-                    }
-                }
-            }
+fn all_fields(count: usize) -> u64 {
+    if count == 0 {
+        0
+    } else {
+        u64::MAX >> (64 - count)
+    }
+}
 
-            if passed {
-                for rule in &rules {
-                    let mut failed = true;
-                    for range in &rule.1 {
-                        if var <= range.1 && var >= range.0 {
-                            failed = false;
-                        }
-                    }
-                    if failed {
-                        positions.get_mut(&rule.0).unwrap().remove(&i);
-                    }
-                }
+/// Sorted closed ranges, with overlapping and touching ones merged.
+#[derive(Debug, PartialEq, Eq)]
+struct IntervalSet {
+    ranges: Vec<(usize, usize)>,
+}
+
+impl IntervalSet {
+    fn new(ranges: impl IntoIterator<Item = (usize, usize)>) -> IntervalSet {
+        let mut sorted = ranges
+            .into_iter()
+            .filter(|(a, b)| a <= b)
+            .collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        let mut merged: Vec<(usize, usize)> = vec![];
+        for (a, b) in sorted {
+            match merged.last_mut() {
+                Some(last) if a <= last.1.saturating_add(1) => last.1 = last.1.max(b),
+                _ => merged.push((a, b)),
             }
         }
+        IntervalSet { ranges: merged }
+    }
+
+    fn contains(&self, x: usize) -> bool {
+        let i = self.ranges.partition_point(|r| r.1 < x);
+        i < self.ranges.len() && self.ranges[i].0 <= x
+    }
+}
+
+/// The number line cut wherever some rule starts or stops applying, with
+/// the fields allowed on each piece as a bitmask.
+struct FieldMasks {
+    starts: Vec<usize>,
+    masks: Vec<u64>,
+}
+
+impl FieldMasks {
+    fn new(fields: &[IntervalSet]) -> FieldMasks {
+        let mut starts = fields
+            .iter()
+            .flat_map(|set| set.ranges.iter())
+            .flat_map(|&(a, b)| vec![a, b.saturating_add(1)])
+            .collect::<Vec<_>>();
+        starts.sort_unstable();
+        starts.dedup();
+
+        let masks = starts
+            .iter()
+            .map(|&x| {
+                fields
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, set)| set.contains(x))
+                    .fold(0, |mask, (f, _)| mask | 1 << f)
+            })
+            .collect();
+        FieldMasks { starts, masks }
+    }
+
+    fn lookup(&self, x: usize) -> u64 {
+        match self.starts.partition_point(|s| *s <= x) {
+            0 => 0,
+            i => self.masks[i - 1],
+        }
     }
+}
+
+struct Validator {
+    valid: IntervalSet,
+    fields: FieldMasks,
+}
 
-    let mut fixed: HashMap<String, usize> = HashMap::new();
+impl Validator {
+    fn new(rules: &[Rule]) -> Result<Validator, Box<dyn Error>> {
+        if rules.len() > 64 {
+            return Err(format!("At most 64 fields are supported, got {}", rules.len()).into());
+        }
+
+        let sets = rules
+            .iter()
+            .map(|rule| IntervalSet::new(rule.1.iter().copied()))
+            .collect::<Vec<_>>();
+        Ok(Validator {
+            valid: IntervalSet::new(sets.iter().flat_map(|set| set.ranges.iter().copied())),
+            fields: FieldMasks::new(&sets),
+        })
+    }
 
-    let mut changes = true;
-    while changes {
-        changes = false;
+    fn invalid_values(&self, ticket: &[usize]) -> Vec<usize> {
+        ticket
+            .iter()
+            .copied()
+            .filter(|var| !self.valid.contains(*var))
+            .collect()
+    }
 
-        for (label, indexes) in &positions {
-            if indexes.len() == 1 {
-                fixed.insert(label.clone(), *indexes.iter().next().unwrap());
-                changes = true;
+    /// For each column, the fields every valid nearby ticket allows there.
+    fn candidates(&self, notes: &Notes) -> Vec<u64> {
+        let mut candidates = vec![all_fields(notes.rules.len()); notes.mine.len()];
+        let valid = notes
+            .nearby
+            .iter()
+            .filter(|ticket| ticket.iter().all(|var| self.valid.contains(*var)));
+        for ticket in valid {
+            for (mask, var) in candidates.iter_mut().zip(ticket) {
+                *mask &= self.fields.lookup(*var);
             }
         }
+        candidates
+    }
+}
+
+fn assign_fields(notes: &Notes) -> Result<BTreeMap<String, usize>, Box<dyn Error>> {
+    let candidates = Validator::new(&notes.rules)?.candidates(notes);
+    let positions: BTreeMap<String, BTreeSet<usize>> = notes
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(f, rule)| {
+            let columns = (0..candidates.len())
+                .filter(|i| candidates[*i] & 1 << f != 0)
+                .collect();
+            (rule.0.clone(), columns)
+        })
+        .collect();
+
+    match Assignment::new(&positions).solve(10) {
+        Solutions::Unique(fixed) => Ok(fixed),
+        Solutions::None { unmatched } => {
+            Err(format!("No column left for {}", unmatched.join(", ")).into())
+        }
+        Solutions::Many(all) => {
+            let options = all
+                .iter()
+                .map(|x| {
+                    x.iter()
+                        .map(|(label, i)| format!("{}={}", label, i))
+                        .join(", ")
+                })
+                .join("; ");
+            Err(format!("Fields fit the columns in more than one way: {}", options).into())
+        }
+    }
+}
 
-        for (label, index) in &fixed {
-            positions.remove(label);
+fn report(buffer: &str) -> Result<String, Box<dyn Error>> {
+    let notes = parse_notes(buffer)?;
+    let validator = Validator::new(&notes.rules)?;
+    let mut out = String::from("Invalid nearby tickets:\n");
+
+    let mut error_rate = 0;
+    for (i, ticket) in notes.nearby.iter().enumerate() {
+        let invalid = validator.invalid_values(ticket);
+        if !invalid.is_empty() {
+            out.push_str(&format!(
+                "  #{} {}: {}\n",
+                i + 1,
+                ticket.iter().join(","),
+                invalid.iter().join(", ")
+            ));
+            error_rate += invalid.iter().sum::<usize>();
+        }
+    }
+    out.push_str(&format!("Error rate: {}\n\n", error_rate));
 
-            for (_, indexes) in positions.iter_mut() {
-                indexes.remove(&index);
+    out.push_str("Your ticket:\n");
+    match assign_fields(&notes) {
+        Ok(fixed) => {
+            let width = fixed.keys().map(|x| x.len()).max().unwrap_or(0).max(5);
+            out.push_str(&format!("  {:<w$}  column  value\n", "field", w = width));
+            for (label, index) in fixed.iter().sorted_by_key(|x| x.1) {
+                out.push_str(&format!(
+                    "  {:<w$}  {:>6}  {:>5}\n",
+                    label,
+                    index,
+                    notes.mine[*index],
+                    w = width
+                ));
             }
         }
+        Err(e) => out.push_str(&format!("  {}\n", e)),
     }
 
+    Ok(out)
+}
+
+/// Replaces the nearby tickets with `count` random ones, mostly made of
+/// values some rule allows so that column elimination has work to do.
+fn synthesise(notes: &mut Notes, count: usize, seed: u64) {
+    let ranges = notes
+        .rules
+        .iter()
+        .flat_map(|rule| rule.1.iter().copied())
+        .collect::<Vec<_>>();
+    let top = ranges.iter().map(|r| r.1).max().unwrap_or(0) + 1;
+
+    let mut rng = Rng::new(seed);
+    notes.nearby = (0..count)
+        .map(|_| {
+            (0..notes.mine.len())
+                .map(|_| {
+                    if ranges.is_empty() || rng.below(100) == 0 {
+                        rng.below(top + top / 10)
+                    } else {
+                        let (a, b) = ranges[rng.below(ranges.len())];
+                        a + rng.below(b - a + 1)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+}
+
+fn bench(buffer: &str, count: usize, seed: u64) -> Result<String, Box<dyn Error>> {
+    let mut notes = parse_notes(buffer)?;
+    synthesise(&mut notes, count, seed);
+
+    let time = |f: &mut dyn FnMut()| {
+        let now = std::time::Instant::now();
+        f();
+        now.elapsed().as_secs_f64() * 1000.0
+    };
+
+    let (mut slow, mut fast) = (0, 0);
+    let linear_scan = time(&mut || {
+        slow = notes
+            .nearby
+            .iter()
+            .flat_map(|ticket| linear_invalid(&notes.rules, ticket))
+            .sum::<usize>()
+    });
+    let mut validator = None;
+    let interval_scan = time(&mut || {
+        let v = Validator::new(&notes.rules).unwrap();
+        fast = notes
+            .nearby
+            .iter()
+            .flat_map(|ticket| v.invalid_values(ticket))
+            .sum::<usize>();
+        validator = Some(v);
+    });
+    if slow != fast {
+        return Err(format!("Error rates differ: linear {}, interval set {}", slow, fast).into());
+    }
+
+    let validator = validator.unwrap();
+    let (mut slow, mut fast) = (vec![], vec![]);
+    let linear_elimination = time(&mut || slow = linear_candidates(&notes));
+    let mask_elimination = time(&mut || fast = validator.candidates(&notes));
+    if slow != fast {
+        return Err("Column candidates differ between linear and field masks".into());
+    }
+
+    Ok(format!(
+        "{} tickets, {} fields\n\
+         scan: linear {:.1}ms, interval set {:.1}ms\n\
+         elimination: linear {:.1}ms, field masks {:.1}ms\n",
+        count,
+        notes.rules.len(),
+        linear_scan,
+        interval_scan,
+        linear_elimination,
+        mask_elimination
+    ))
+}
+
+fn solve1(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    let notes = parse_notes(buffer)?;
+    let validator = Validator::new(&notes.rules)?;
+    Ok(notes
+        .nearby
+        .iter()
+        .flat_map(|ticket| validator.invalid_values(ticket))
+        .sum())
+}
+
+fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    let notes = parse_notes(buffer)?;
+    let fixed = assign_fields(&notes)?;
+
     let mut result = 1;
     for (label, index) in fixed {
         if label.contains("departure") {
-            result *= myticket[index];
+            result *= notes.mine[index];
         }
     }
 
@@ -138,7 +409,13 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "2" {
+    if args.len() > 1 && args[1] == "bench" {
+        let count = args.get(2).map_or(Ok(100_000), |x| x.parse::<usize>())?;
+        let seed = args.get(3).map_or(Ok(0), |x| x.parse::<u64>())?;
+        print!("{}", bench(&buffer, count, seed)?);
+    } else if args.len() > 1 && args[1] == "report" {
+        print!("{}", report(&buffer)?);
+    } else if args.len() > 1 && args[1] == "2" {
         println!("{}", solve2(&buffer)?);
     } else {
         println!("{}", solve1(&buffer)?);
@@ -152,7 +429,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod test {
     use super::*;
 
-    const INPUT: &'static str = "class;: 1-3 or 5-7
+    const INPUT: &str = "class;: 1-3 or 5-7
 row: 6-11 or 33-44
 seat: 13-40 or 45-50
 
@@ -174,4 +451,113 @@ nearby tickets:
     fn test2() {
         assert_eq!(solve2(INPUT).unwrap(), 1);
     }
+
+    #[test]
+    fn test_assignment() {
+        let input = "departure class: 0-1 or 4-19
+departure row: 0-5 or 8-19
+seat: 0-13 or 16-19
+
+your ticket:
+11,12,13
+
+nearby tickets:
+3,9,18
+15,1,5
+5,14,9";
+        assert_eq!(solve2(input).unwrap(), 12 * 11);
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let input = "a: 0-10 or 20-30
+b: 0-10 or 20-30
+
+your ticket:
+1,2
+
+nearby tickets:
+3,4";
+        assert_eq!(
+            solve2(input).unwrap_err().to_string(),
+            "Fields fit the columns in more than one way: a=0, b=1; a=1, b=0"
+        );
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        assert_eq!(
+            parse("arrival track: 1-3").unwrap(),
+            (String::from("arrival track"), vec![(1, 3)])
+        );
+        assert_eq!(
+            parse("zone: 1-3 or 5-7 or 10-12").unwrap().1,
+            vec![(1, 3), (5, 7), (10, 12)]
+        );
+        assert!(parse("zone: 1-3 and 5-7").is_err());
+        assert_eq!(
+            parse("zone: 1-3 or 7-5").unwrap_err().to_string(),
+            "Range 7-5 in rule 'zone: 1-3 or 7-5' is backwards"
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let input = "class: 0-1 or 4-19
+row: 0-5 or 8-19
+seat: 0-13 or 16-19 or 25-25
+
+your ticket:
+11,12,13
+
+nearby tickets:
+3,9,18
+15,1,5
+5,14,9
+20,25,30";
+        assert_eq!(
+            report(input).unwrap(),
+            "Invalid nearby tickets:
+  #4 20,25,30: 20, 30
+Error rate: 50
+
+Your ticket:
+  field  column  value
+  row         0     11
+  class       1     12
+  seat        2     13
+"
+        );
+    }
+
+    #[test]
+    fn test_interval_set() {
+        let set = IntervalSet::new(vec![(10, 12), (1, 3), (4, 5), (2, 2), (20, 25), (21, 22)]);
+        assert_eq!(set.ranges, vec![(1, 5), (10, 12), (20, 25)]);
+        for (x, expected) in [(0, false), (1, true), (5, true), (6, false), (12, true)] {
+            assert_eq!(set.contains(x), expected, "{}", x);
+        }
+        assert!(!set.contains(26));
+        assert!(!IntervalSet::new(vec![]).contains(0));
+    }
+
+    #[test]
+    fn test_field_masks() {
+        let notes = parse_notes(INPUT).unwrap();
+        let validator = Validator::new(&notes.rules).unwrap();
+        for x in 0..60 {
+            let expected = notes
+                .rules
+                .iter()
+                .enumerate()
+                .filter(|(_, rule)| allows(rule, x))
+                .fold(0, |mask, (f, _)| mask | 1 << f);
+            assert_eq!(validator.fields.lookup(x), expected, "{}", x);
+        }
+
+        let mut notes = parse_notes(INPUT).unwrap();
+        synthesise(&mut notes, 500, 3);
+        assert_eq!(validator.candidates(&notes), linear_candidates(&notes));
+        assert!(bench(INPUT, 500, 3).is_ok());
+    }
 }
@@ -1,144 +1,266 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::error::Error;
-use std::io::{self, Read};
+use std::fs::File;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::io::{self, Read, Write};
 
-struct Neighbors {
-    ranges: Vec<(i64, i64)>,
-    state: Vec<i64>,
-    counter: usize,
-    total: usize,
-    dims: usize,
-}
+use itertools::Itertools;
 
-impl Neighbors {
-    fn new(root: &Vec<i64>) -> Neighbors {
-        Neighbors::new_ranges(root.iter().map(|x| (x - 1, x + 1)).collect::<Vec<_>>())
-    }
+const MAX_DIMS: usize = 8;
 
-    fn new_ranges(ranges: Vec<(i64, i64)>) -> Neighbors {
-        let dims = ranges.len();
-        let total = ranges.iter().map(|(a, b)| (b - a + 1) as usize).product();
-        let state = ranges.iter().map(|(a, _)| *a).collect::<Vec<_>>();
+/// The full pocket grows too slowly to simulate from this many dimensions
+/// on, so only the symmetric one is allowed.
+const SYMMETRIC_FROM: usize = 7;
 
-        Neighbors {
-            ranges,
-            dims,
-            counter: 0,
-            total,
-            state,
-        }
-    }
-}
-
-impl Iterator for Neighbors {
-    type Item = Vec<i64>;
+/// x and y from the input first, then the other dimensions; anything past
+/// `dims` stays 0.
+type Point = [i16; MAX_DIMS];
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.counter >= self.total {
-            return None;
-        }
-        self.counter += 1;
+const AXES: [&str; MAX_DIMS] = ["x", "y", "z", "w", "v", "u", "t", "s"];
 
-        let result = Some(self.state.clone());
+/// Multiply-and-rotate hashing in the style of rustc's FxHasher: the
+/// default SipHash dominates the run time with millions of tiny keys.
+#[derive(Default)]
+struct PointHasher(u64);
 
-        for i in (0..self.dims).rev() {
-            self.state[i] += 1;
-            if self.state[i] > self.ranges[i].1 {
-                self.state[i] = self.ranges[i].0
-            } else {
-                break;
-            }
+impl Hasher for PointHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(word));
         }
+    }
 
-        result
+    fn write_u64(&mut self, x: u64) {
+        self.0 = (self.0.rotate_left(5) ^ x).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
     }
-}
 
-fn print_cube(hypercube: &HashMap<Vec<i64>, bool>, ranges: &Vec<(i64, i64)>) {
-    for outer_path in Neighbors::new_ranges(ranges[..ranges.len() - 2].to_vec()) {
-        println!("{:?}", outer_path);
-        let y_range = ranges[ranges.len() - 2];
-        let x_range = ranges[ranges.len() - 1];
-        for y in y_range.0..=y_range.1 {
-            for x in x_range.0..=x_range.1 {
-                let mut path = outer_path.clone();
-                path.push(y);
-                path.push(x);
-                let pt = hypercube.get(&path).unwrap_or(&false);
-                print!("{} ", if *pt { '#' } else { '.' });
-            }
-            println!();
-        }
+    fn write_usize(&mut self, x: usize) {
+        self.write_u64(x as u64);
+    }
 
-        println!();
+    fn finish(&self) -> u64 {
+        self.0
     }
 }
 
-fn solve(buffer: &str, dims: usize) -> usize {
-    assert!(dims >= 2);
-
-    let mut hypercube: HashMap<Vec<i64>, bool> = HashMap::new();
-    let mut next = hypercube.clone();
+type Build = BuildHasherDefault<PointHasher>;
 
-    let mut ranges: Vec<(i64, i64)> = vec![(0, 0); dims];
+struct Pocket {
+    dims: usize,
+    active: HashSet<Point, Build>,
+    /// Beyond x and y everything starts at 0, so the pocket stays mirror
+    /// symmetric in each further dimension. If set, only cells with those
+    /// coordinates non-negative are kept, each standing for its mirror images.
+    symmetric: bool,
+}
 
-    for (y, line) in buffer.lines().enumerate() {
-        for (x, ch) in line.chars().enumerate() {
-            let mut path = vec![0 as i64; dims];
-            path[dims - 1] = x as i64;
-            path[dims - 2] = y as i64;
+impl Pocket {
+    fn parse(buffer: &str, dims: usize) -> Pocket {
+        assert!((2..=MAX_DIMS).contains(&dims));
+
+        let mut active = HashSet::default();
+        for (y, line) in buffer.lines().enumerate() {
+            for (x, ch) in line.chars().enumerate() {
+                let mut point = [0; MAX_DIMS];
+                point[0] = x as i16;
+                point[1] = y as i16;
+
+                match ch {
+                    '.' => {}
+                    '#' => {
+                        active.insert(point);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
 
-            let pt = hypercube.entry(path).or_insert(false);
-            *pt = match ch {
-                '.' => false,
-                '#' => true,
-                _ => unreachable!(),
-            };
+        Pocket {
+            dims,
+            active,
+            symmetric: false,
+        }
+    }
 
-            ranges[dims - 1].1 = x as i64;
+    /// Counts the active cells in the 3x3x... block around every cell near
+    /// an active one, one axis at a time: summing runs of three along x,
+    /// then along y and so on adds up the whole block with 3 * dims visits
+    /// per cell instead of 3^dims. The block includes the cell itself.
+    fn step(&mut self) {
+        let mut counts: HashMap<Point, u16, Build> =
+            self.active.iter().map(|cell| (*cell, 1)).collect();
+        for i in 0..self.dims {
+            let mut next: HashMap<Point, u16, Build> =
+                HashMap::with_capacity_and_hasher(counts.len() * 2, Build::default());
+            let mirrored = self.symmetric && i >= 2;
+            for (cell, n) in counts {
+                for d in -1..=1 {
+                    let mut x = cell;
+                    x[i] += d;
+                    if mirrored && x[i] < 0 {
+                        continue;
+                    }
+                    *next.entry(x).or_insert(0) += n;
+                }
+                // The mirror image at -1 is a neighbour of 0 too
+                if mirrored && cell[i] == 1 {
+                    let mut x = cell;
+                    x[i] = 0;
+                    *next.entry(x).or_insert(0) += n;
+                }
+            }
+            counts = next;
         }
 
-        ranges[dims - 2].1 = y as i64;
+        self.active = counts
+            .into_iter()
+            .filter(|(cell, n)| *n == 3 || (*n == 4 && self.active.contains(cell)))
+            .map(|(cell, _)| cell)
+            .collect();
     }
 
-    for _ in 0..6 {
-        let current_ranges = ranges.iter().map(|(a, b)| (a - 1, b + 1)).collect::<Vec<_>>();
-        ranges = vec![(0, 0); dims];
+    /// Active cells, counting every mirror image when symmetric.
+    fn count(&self) -> usize {
+        if !self.symmetric {
+            return self.active.len();
+        }
+        self.active
+            .iter()
+            .map(|cell| 1 << cell[2..self.dims].iter().filter(|x| **x != 0).count())
+            .sum()
+    }
 
-        for path in Neighbors::new_ranges(current_ranges) {
-            let current = hypercube.get(&path).unwrap_or(&false);
-            let neighbors = Neighbors::new(&path)
-                .filter(|n| *n != path)
-                .filter_map(|n| hypercube.get(&n))
-                .filter(|a| **a)
-                .count();
+    /// The active cells, with the mirror images filled back in.
+    fn cells(&self) -> Vec<Point> {
+        let mut cells = self.active.iter().copied().collect::<Vec<_>>();
+        if self.symmetric {
+            for i in 2..self.dims {
+                let mirrored = cells
+                    .iter()
+                    .filter(|x| x[i] != 0)
+                    .map(|x| {
+                        let mut x = *x;
+                        x[i] = -x[i];
+                        x
+                    })
+                    .collect::<Vec<_>>();
+                cells.extend(mirrored);
+            }
+        }
+        cells
+    }
 
-            let next_state = match (current, neighbors) {
-                (true, 2) | (_, 3) => true,
-                _ => false,
-            };
+    /// Every slice parallel to the `plane` axes that has an active cell,
+    /// labelled with its other coordinates and drawn over the same window.
+    fn slices(&self, plane: (usize, usize)) -> String {
+        let cells = self.cells().into_iter().collect::<HashSet<_>>();
+        let (cols, rows) = match (
+            cells.iter().map(|x| x[plane.0]).minmax().into_option(),
+            cells.iter().map(|x| x[plane.1]).minmax().into_option(),
+        ) {
+            (Some(cols), Some(rows)) => (cols, rows),
+            _ => return String::new(),
+        };
+
+        let outer = (0..self.dims)
+            .filter(|i| *i != plane.0 && *i != plane.1)
+            .collect::<Vec<_>>();
+        let mut out = String::new();
+        for slice in cells
+            .iter()
+            .map(|x| outer.iter().map(|i| x[*i]).collect::<Vec<_>>())
+            .unique()
+            .sorted()
+        {
+            if !outer.is_empty() {
+                let label = outer
+                    .iter()
+                    .zip(&slice)
+                    .map(|(i, x)| format!("{}={}", AXES[*i], x))
+                    .join(", ");
+                out.push_str(&label);
+                out.push('\n');
+            }
 
-            if next_state {
-                for i in 0..dims {
-                    if path[i] > ranges[i].1 {
-                        ranges[i].1 = path[i];
-                    } else if path[i] < ranges[i].0 {
-                        ranges[i].0 = path[i];
-                    }
+            let mut point = [0; MAX_DIMS];
+            for (i, x) in outer.iter().zip(&slice) {
+                point[*i] = *x;
+            }
+            for row in rows.0..=rows.1 {
+                for col in cols.0..=cols.1 {
+                    point[plane.0] = col;
+                    point[plane.1] = row;
+                    out.push(if cells.contains(&point) { '#' } else { '.' });
                 }
+                out.push('\n');
             }
+            out.push('\n');
+        }
+        out
+    }
 
-            next.insert(path, next_state);
+    fn run(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.step();
         }
+    }
+}
 
-        let temp = hypercube;
-        hypercube = next;
-        next = temp;
-        // print_cube(&hypercube, &ranges);
+fn check_dims(dims: usize, symmetric: bool) -> Result<(), Box<dyn Error>> {
+    if !(2..=MAX_DIMS).contains(&dims) {
+        return Err(format!("Dimensions must be between 2 and {}", MAX_DIMS).into());
+    }
+    if dims >= SYMMETRIC_FROM && !symmetric {
+        return Err(format!(
+            "{} or more dimensions are only simulated with 'symmetric'",
+            SYMMETRIC_FROM
+        )
+        .into());
     }
+    Ok(())
+}
 
-    hypercube.values().filter(|a| **a).count()
+fn simulate(buffer: &str, dims: usize, cycles: usize, symmetric: bool) -> usize {
+    let mut pocket = Pocket::parse(buffer, dims);
+    pocket.symmetric = symmetric;
+    pocket.run(cycles);
+    pocket.count()
+}
+
+/// Writes the slices after each of `cycles`, in the style of the puzzle text.
+fn export(
+    pocket: &mut Pocket,
+    cycles: &[usize],
+    plane: (usize, usize),
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let mut done = 0;
+    for &cycle in cycles.iter().sorted().dedup() {
+        pocket.run(cycle - done);
+        done = cycle;
+
+        match cycle {
+            0 => writeln!(out, "Before any cycles:\n")?,
+            1 => writeln!(out, "After 1 cycle:\n")?,
+            _ => writeln!(out, "After {} cycles:\n", cycle)?,
+        }
+        write!(out, "{}", pocket.slices(plane))?;
+    }
+    Ok(())
+}
+
+fn axis(name: &str, dims: usize) -> Result<usize, Box<dyn Error>> {
+    AXES[..dims]
+        .iter()
+        .position(|x| *x == name)
+        .ok_or_else(|| format!("No axis '{}' in {} dimensions", name, dims).into())
+}
+
+fn solve(buffer: &str, dims: usize) -> usize {
+    simulate(buffer, dims, 6, true)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -148,7 +270,48 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "2" {
+    if args.len() > 2 && args[1] == "dims" {
+        // dims <dims> [cycles] [symmetric]
+        let dims = args[2].parse::<usize>()?;
+        let cycles = args.get(3).map_or(Ok(6), |x| x.parse::<usize>())?;
+        let symmetric = args.len() > 4 && args[4] == "symmetric";
+        check_dims(dims, symmetric)?;
+        println!("{}", simulate(&buffer, dims, cycles, symmetric));
+    } else if args.len() > 2 && args[1] == "slices" {
+        // slices <dims> [cycles=0,1,..] [plane=x,y] [out=file] [symmetric]
+        let dims = args[2].parse::<usize>()?;
+        let symmetric = args[3..].iter().any(|x| x == "symmetric");
+        check_dims(dims, symmetric)?;
+        let mut pocket = Pocket::parse(&buffer, dims);
+        pocket.symmetric = symmetric;
+        let mut cycles = (0..=6).collect::<Vec<_>>();
+        let mut plane = (0, 1);
+        let mut out: Box<dyn Write> = Box::new(io::stdout());
+
+        for arg in &args[3..] {
+            if let Some(list) = arg.strip_prefix("cycles=") {
+                cycles = list
+                    .split(',')
+                    .map(|x| x.parse::<usize>())
+                    .collect::<Result<Vec<_>, _>>()?;
+            } else if let Some(axes) = arg.strip_prefix("plane=") {
+                let axes = axes
+                    .split(',')
+                    .map(|x| axis(x, dims))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if axes.len() != 2 || axes[0] == axes[1] {
+                    return Err("A plane needs two different axes".into());
+                }
+                plane = (axes[0], axes[1]);
+            } else if let Some(path) = arg.strip_prefix("out=") {
+                out = Box::new(File::create(path)?);
+            } else if arg != "symmetric" {
+                return Err(format!("Unknown option '{}'", arg).into());
+            }
+        }
+
+        export(&mut pocket, &cycles, plane, &mut out)?;
+    } else if args.len() > 1 && args[1] == "2" {
         println!("{}", solve(&buffer, 4));
     } else {
         println!("{}", solve(&buffer, 3));
@@ -162,11 +325,10 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod test {
     use super::*;
 
-    const INPUT: &'static str = ".#.
+    const INPUT: &str = ".#.
 ..#
 ###";
 
-
     #[test]
     fn test1() {
         assert_eq!(solve(INPUT, 3), 112);
@@ -176,4 +338,93 @@ mod test {
     fn test2() {
         assert_eq!(solve(INPUT, 4), 848);
     }
+
+    #[test]
+    fn test_cycles() {
+        assert_eq!(simulate(INPUT, 3, 0, false), 5);
+        assert_eq!(simulate(INPUT, 3, 1, false), 11);
+        assert_eq!(simulate(INPUT, 3, 3, false), 38);
+        assert_eq!(simulate(INPUT, 2, 6, false), 5);
+    }
+
+    #[test]
+    fn test_5d() {
+        assert_eq!(solve(INPUT, 5), 5760);
+    }
+
+    #[test]
+    fn test_6d() {
+        assert_eq!(simulate(INPUT, 6, 6, true), 35936);
+
+        assert!(check_dims(6, false).is_ok());
+        assert!(check_dims(7, true).is_ok());
+        assert!(check_dims(7, false).is_err());
+        assert!(check_dims(9, true).is_err());
+    }
+
+    #[test]
+    fn test_symmetric() {
+        for dims in 2..=5 {
+            for cycles in 0..=6 {
+                assert_eq!(
+                    simulate(INPUT, dims, cycles, true),
+                    simulate(INPUT, dims, cycles, false),
+                    "{} dimensions, {} cycles",
+                    dims,
+                    cycles
+                );
+            }
+        }
+
+        let mut full = Pocket::parse(INPUT, 4);
+        let mut half = Pocket::parse(INPUT, 4);
+        half.symmetric = true;
+        full.run(6);
+        half.run(6);
+        assert!(half.active.iter().all(|x| x[2] >= 0 && x[3] >= 0));
+        assert!(half.active.len() * 3 < full.active.len());
+    }
+
+    #[test]
+    fn test_slices() {
+        let mut pocket = Pocket::parse(INPUT, 3);
+        let mut out = vec![];
+        export(&mut pocket, &[1], (0, 1), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "After 1 cycle:
+
+z=-1
+#..
+..#
+.#.
+
+z=0
+#.#
+.##
+.#.
+
+z=1
+#..
+..#
+.#.
+
+"
+        );
+
+        // Mirror images come back for export
+        for plane in &[(0, 1), (2, 3), (1, 3)] {
+            let mut full = Pocket::parse(INPUT, 4);
+            let mut half = Pocket::parse(INPUT, 4);
+            half.symmetric = true;
+            let (mut a, mut b) = (vec![], vec![]);
+            export(&mut full, &[2, 0], *plane, &mut a).unwrap();
+            export(&mut half, &[2, 0], *plane, &mut b).unwrap();
+            assert_eq!(a, b);
+        }
+
+        assert_eq!(Pocket::parse(INPUT, 2).slices((0, 1)), ".#.\n..#\n###\n\n");
+        assert_eq!(axis("w", 4).unwrap(), 3);
+        assert!(axis("w", 3).is_err());
+    }
 }
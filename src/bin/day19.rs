@@ -1,38 +1,70 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::env;
 use std::error::Error;
 use std::io::{self, Read};
 
-fn parse(buffer: &str) -> (HashMap<&str, Vec<Vec<&str>>>, Vec<&str>) {
+use aoc2020::rng::Rng;
+
+type Rules<'a> = HashMap<&'a str, Vec<Vec<&'a str>>>;
+
+fn parse(buffer: &str) -> Result<(Rules<'_>, Vec<&str>), Box<dyn Error>> {
     let pieces = buffer.split("\n\n").collect::<Vec<_>>();
 
     let rules = pieces[0]
+        .trim()
         .split("\n")
         .map(|x| x.split(":").collect::<Vec<_>>())
         .map(|v| {
-            (
+            if v.len() != 2 {
+                return Err(format!("Can't parse rule '{}'", v.join(":")));
+            }
+            Ok((
                 v[0],
                 v[1].split("|")
                     .map(|p| p.split(" ").filter(|x| x != &"").collect::<Vec<_>>())
                     .collect::<Vec<_>>(),
-            )
+            ))
         })
-        .collect::<HashMap<_, _>>();
+        .collect::<Result<Rules, _>>()?;
+
+    if !rules.contains_key("0") {
+        return Err("No rule 0 to match messages against".into());
+    }
+    for (name, parts) in &rules {
+        for item in parts.iter().flatten() {
+            if literal(item).is_none() && !rules.contains_key(item) {
+                return Err(format!(
+                    "Rule {} refers to '{}', which is neither a rule nor a literal",
+                    name, item
+                )
+                .into());
+            }
+        }
+    }
 
-    let messages = pieces[1].split("\n").collect::<Vec<_>>();
+    let messages = pieces
+        .get(1)
+        .map_or(vec![], |x| x.split("\n").collect::<Vec<_>>());
 
-    (rules, messages)
+    Ok((rules, messages))
 }
 
-fn apply<'a>(
-    r: &str,
-    rules: &'a HashMap<&'a str, Vec<Vec<&'a str>>>,
-    msg: &'a str,
-    prefix: String,
-) -> Vec<&'a str> {
+fn loop_rules(rules: &mut Rules) {
+    rules.insert("8", vec![vec!["42"], vec!["42", "8"]]);
+    rules.insert("11", vec![vec!["42", "31"], vec!["42", "11", "31"]]);
+}
+
+fn literal(item: &str) -> Option<&str> {
+    if item.len() >= 2 && item.starts_with('"') && item.ends_with('"') {
+        Some(&item[1..item.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn apply<'a>(r: &str, rules: &'a Rules<'a>, msg: &'a str) -> Vec<&'a str> {
     let rule = rules.get(r).unwrap();
     let mut results: Vec<&'a str> = vec![];
-    //println!("{}{} {:?} {}", prefix, r, rule, msg);
 
     for part in rule {
         let mut leftovers = vec![msg];
@@ -40,19 +72,16 @@ fn apply<'a>(
 
         for item in part {
             for leftover in &leftovers {
-                if let Ok(x) = item.parse::<u64>() {
-                    let x = x.to_string();
-                    let inner_results = apply(&x, rules, leftover, format!("{} ", prefix));
-
-                    for inner_result in inner_results {
+                if let Some(lit) = literal(item) {
+                    if let Some(rest) = leftover.strip_prefix(lit) {
+                        next_leftovers.push(rest);
+                    }
+                } else {
+                    for inner_result in apply(item, rules, leftover) {
                         if &inner_result != leftover {
                             next_leftovers.push(inner_result);
                         }
                     }
-                } else {
-                    if leftover.starts_with(&item[1..item.len() - 1]) {
-                        next_leftovers.push(&leftover[1..]);
-                    }
                 }
             }
 
@@ -63,35 +92,372 @@ fn apply<'a>(
         results.append(&mut leftovers);
     }
 
-    //println!("{}{} {} > {:?}", prefix, r, msg, results);
     results
 }
 
-fn solve1(buffer: &str) -> usize {
-    let (rules, messages) = parse(buffer);
-    messages
-        .iter()
-        .filter(|m| {
-            apply("0", &rules, m, String::from(""))
-                .iter()
-                .any(|x| x == &"")
-        })
-        .count()
+fn matches(rules: &Rules, msg: &str) -> bool {
+    apply("0", rules, msg).iter().any(|x| x.is_empty())
 }
 
-fn solve2(buffer: &str) -> usize {
-    let (mut rules, messages) = parse(buffer);
-    rules.insert("8", vec![vec!["42"], vec!["42", "8"]]);
-    rules.insert("11", vec![vec!["42", "31"], vec!["42", "11", "31"]]);
+/// How a rule consumed (part of) a message.
+#[derive(Debug, Clone)]
+enum Derivation<'a> {
+    Literal(&'a str),
+    Rule {
+        rule: &'a str,
+        alternative: usize,
+        children: Vec<Derivation<'a>>,
+    },
+}
 
-    messages
-        .iter()
-        .filter(|m| {
-            apply("0", &rules, m, String::from(""))
-                .iter()
-                .any(|x| x == &"")
-        })
-        .count()
+/// The chain of rule alternatives (outermost first) that could not continue
+/// at the failure point; `expected` is `None` when rule 0 matched but the
+/// message kept going.
+#[derive(Debug, PartialEq, Eq)]
+struct Attempt<'a> {
+    path: Vec<(&'a str, usize)>,
+    expected: Option<&'a str>,
+}
+
+#[derive(Debug, Default)]
+struct Failures<'a> {
+    position: usize,
+    attempts: Vec<Attempt<'a>>,
+}
+
+#[derive(Debug)]
+enum Explanation<'a> {
+    Match(Derivation<'a>),
+    NoMatch {
+        prefix: &'a str,
+        attempts: Vec<Attempt<'a>>,
+    },
+}
+
+impl<'a> Failures<'a> {
+    fn record(&mut self, position: usize, attempt: Attempt<'a>) {
+        if position > self.position {
+            self.position = position;
+            self.attempts.clear();
+        }
+        if position == self.position && !self.attempts.contains(&attempt) {
+            self.attempts.push(attempt);
+        }
+    }
+}
+
+/// Same traversal as `apply`, but keeps the derivation for every leftover
+/// and records the furthest point any alternative failed at.
+fn derive<'a>(
+    r: &'a str,
+    rules: &'a Rules<'a>,
+    msg: &'a str,
+    total: usize,
+    path: &mut Vec<(&'a str, usize)>,
+    failures: &mut Failures<'a>,
+) -> Vec<(Derivation<'a>, &'a str)> {
+    let rule = rules.get(r).unwrap();
+    let mut results = vec![];
+
+    for (alternative, part) in rule.iter().enumerate() {
+        path.push((r, alternative));
+        let mut partials: Vec<(Vec<Derivation<'a>>, &'a str)> = vec![(vec![], msg)];
+
+        for item in part {
+            let mut next_partials = vec![];
+            for (children, leftover) in &partials {
+                if let Some(lit) = literal(item) {
+                    if let Some(rest) = leftover.strip_prefix(lit) {
+                        let mut children = children.clone();
+                        children.push(Derivation::Literal(lit));
+                        next_partials.push((children, rest));
+                    } else {
+                        let attempt = Attempt {
+                            path: path.clone(),
+                            expected: Some(lit),
+                        };
+                        failures.record(total - leftover.len(), attempt);
+                    }
+                } else {
+                    let inner_results = derive(item, rules, leftover, total, path, failures);
+                    for (inner, inner_leftover) in inner_results {
+                        if inner_leftover != *leftover {
+                            let mut children = children.clone();
+                            children.push(inner);
+                            next_partials.push((children, inner_leftover));
+                        }
+                    }
+                }
+            }
+            partials = next_partials;
+        }
+        path.pop();
+
+        for (children, leftover) in partials {
+            let derivation = Derivation::Rule {
+                rule: r,
+                alternative,
+                children,
+            };
+            results.push((derivation, leftover));
+        }
+    }
+
+    results
+}
+
+fn explain_message<'a>(rules: &'a Rules<'a>, msg: &'a str) -> Explanation<'a> {
+    let mut failures = Failures::default();
+    let mut results = derive("0", rules, msg, msg.len(), &mut vec![], &mut failures);
+
+    if let Some(i) = results.iter().position(|(_, leftover)| leftover.is_empty()) {
+        return Explanation::Match(results.swap_remove(i).0);
+    }
+
+    for (derivation, leftover) in results {
+        if let Derivation::Rule { alternative, .. } = derivation {
+            let attempt = Attempt {
+                path: vec![("0", alternative)],
+                expected: None,
+            };
+            failures.record(msg.len() - leftover.len(), attempt);
+        }
+    }
+
+    Explanation::NoMatch {
+        prefix: &msg[..failures.position],
+        attempts: failures.attempts,
+    }
+}
+
+fn format_derivation(derivation: &Derivation, rules: &Rules, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    match derivation {
+        Derivation::Literal(lit) => out.push_str(&format!("{}\"{}\"\n", indent, lit)),
+        Derivation::Rule {
+            rule,
+            alternative,
+            children,
+        } => {
+            out.push_str(&format!(
+                "{}{}: {}\n",
+                indent,
+                rule,
+                rules[rule][*alternative].join(" ")
+            ));
+            if children.iter().all(|c| matches!(c, Derivation::Literal(_))) {
+                return;
+            }
+            for child in children {
+                format_derivation(child, rules, depth + 1, out);
+            }
+        }
+    }
+}
+
+fn explain(buffer: &str, looped: bool) -> Result<String, Box<dyn Error>> {
+    let (mut rules, messages) = parse(buffer)?;
+    if looped {
+        loop_rules(&mut rules);
+    }
+
+    let mut out = String::new();
+    for msg in messages.iter().filter(|m| !m.is_empty()) {
+        match explain_message(&rules, msg) {
+            Explanation::Match(derivation) => {
+                out.push_str(&format!("{}: match\n", msg));
+                format_derivation(&derivation, &rules, 1, &mut out);
+            }
+            Explanation::NoMatch { prefix, attempts } => {
+                out.push_str(&format!("{}: no match\n", msg));
+                out.push_str(&format!(
+                    "  longest matched prefix: \"{}\" ({} of {})\n",
+                    prefix,
+                    prefix.len(),
+                    msg.len()
+                ));
+                out.push_str(&format!("  tried at position {}:\n", prefix.len()));
+                for attempt in attempts {
+                    let path = attempt
+                        .path
+                        .iter()
+                        .map(|(rule, alternative)| {
+                            format!("{}: {}", rule, rules[rule][*alternative].join(" "))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" / ");
+                    out.push_str(&format!(
+                        "    {} expected {}\n",
+                        path,
+                        attempt
+                            .expected
+                            .map_or(String::from("end of message"), |x| format!("\"{}\"", x))
+                    ));
+                }
+            }
+        }
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+/// Runs the grammar backwards to produce messages, bounded by length.
+struct Generator<'a> {
+    rules: &'a Rules<'a>,
+    min_lengths: HashMap<&'a str, usize>,
+    cache: HashMap<(&'a str, usize), Vec<String>>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(rules: &'a Rules<'a>) -> Generator<'a> {
+        // Shortest message per rule, relaxed until nothing improves
+        let mut min_lengths: HashMap<&str, usize> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (r, alternatives) in rules {
+                let best = alternatives
+                    .iter()
+                    .filter_map(|part| Generator::part_min(&min_lengths, part))
+                    .min();
+                if let Some(best) = best {
+                    if min_lengths.get(r).is_none_or(|x| best < *x) {
+                        min_lengths.insert(r, best);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        Generator {
+            rules,
+            min_lengths,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn part_min(min_lengths: &HashMap<&str, usize>, part: &[&str]) -> Option<usize> {
+        part.iter()
+            .map(|item| match literal(item) {
+                Some(lit) => Some(lit.len()),
+                None => min_lengths.get(item).copied(),
+            })
+            .sum()
+    }
+
+    /// Every message `r` derives with at most `max_len` characters, sorted.
+    fn enumerate(&mut self, r: &'a str, max_len: usize) -> Vec<String> {
+        if let Some(cached) = self.cache.get(&(r, max_len)) {
+            return cached.clone();
+        }
+        // Placeholder so a rule that derives itself without consuming
+        // anything terminates instead of recursing forever.
+        self.cache.insert((r, max_len), vec![]);
+
+        let rules = self.rules;
+        let mut results = BTreeSet::new();
+        for part in &rules[r] {
+            let mut partials = vec![String::new()];
+            for (i, item) in part.iter().enumerate() {
+                let rest = match Generator::part_min(&self.min_lengths, &part[i + 1..]) {
+                    Some(rest) => rest,
+                    None => {
+                        partials.clear();
+                        break;
+                    }
+                };
+
+                let mut next_partials = vec![];
+                for partial in &partials {
+                    if partial.len() + rest > max_len {
+                        continue;
+                    }
+                    let budget = max_len - partial.len() - rest;
+                    let expansions = match literal(item) {
+                        Some(lit) if lit.len() <= budget => vec![lit.to_string()],
+                        Some(_) => vec![],
+                        None => self.enumerate(item, budget),
+                    };
+                    for expansion in expansions {
+                        next_partials.push(format!("{}{}", partial, expansion));
+                    }
+                }
+                partials = next_partials;
+            }
+            results.extend(partials);
+        }
+
+        let results = results.into_iter().collect::<Vec<_>>();
+        self.cache.insert((r, max_len), results.clone());
+        results
+    }
+
+    /// A random message from `r` with at most `max_len` characters, or
+    /// `None` if the rule can't produce anything that short.
+    fn sample(&self, r: &'a str, max_len: usize, rng: &mut Rng) -> Option<String> {
+        let options = self.rules[r]
+            .iter()
+            .filter(|part| {
+                Generator::part_min(&self.min_lengths, part).is_some_and(|x| x <= max_len)
+            })
+            .collect::<Vec<_>>();
+        if options.is_empty() {
+            return None;
+        }
+
+        let part = options[rng.below(options.len())];
+        let mut message = String::new();
+        for (i, item) in part.iter().enumerate() {
+            let rest = Generator::part_min(&self.min_lengths, &part[i + 1..])?;
+            let budget = max_len - message.len() - rest;
+            match literal(item) {
+                Some(lit) => message.push_str(lit),
+                None => message.push_str(&self.sample(item, budget, rng)?),
+            }
+        }
+
+        Some(message)
+    }
+}
+
+/// Rules followed by generated messages, i.e. a ready-made puzzle input.
+/// Enumerates everything up to `max_len` unless `samples` asks for
+/// `(count, seed)` random messages instead.
+fn generate(
+    buffer: &str,
+    max_len: usize,
+    samples: Option<(usize, u64)>,
+) -> Result<String, Box<dyn Error>> {
+    let (rules, _) = parse(buffer)?;
+    let mut generator = Generator::new(&rules);
+
+    let messages = match samples {
+        Some((count, seed)) => {
+            let mut rng = Rng::new(seed);
+            (0..count)
+                .filter_map(|_| generator.sample("0", max_len, &mut rng))
+                .collect::<Vec<_>>()
+        }
+        None => generator.enumerate("0", max_len),
+    };
+
+    Ok(format!(
+        "{}\n\n{}\n",
+        buffer.split("\n\n").next().unwrap().trim(),
+        messages.join("\n")
+    ))
+}
+
+fn solve1(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    let (rules, messages) = parse(buffer)?;
+    Ok(messages.iter().filter(|m| matches(&rules, m)).count())
+}
+
+fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    let (mut rules, messages) = parse(buffer)?;
+    loop_rules(&mut rules);
+    Ok(messages.iter().filter(|m| matches(&rules, m)).count())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -101,10 +467,22 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "2" {
-        println!("{}", solve2(&buffer));
+    if args.len() > 1 && args[1] == "explain" {
+        print!("{}", explain(&buffer, args.len() > 2 && args[2] == "2")?);
+    } else if args.len() > 2 && args[1] == "generate" {
+        let max_len = args[2].parse::<usize>()?;
+        let samples = match args.get(3) {
+            Some(count) => Some((
+                count.parse::<usize>()?,
+                args.get(4).map_or(Ok(0), |x| x.parse::<u64>())?,
+            )),
+            None => None,
+        };
+        print!("{}", generate(&buffer, max_len, samples)?);
+    } else if args.len() > 1 && args[1] == "2" {
+        println!("{}", solve2(&buffer)?);
     } else {
-        println!("{}", solve1(&buffer));
+        println!("{}", solve1(&buffer)?);
     }
 
     eprintln!("Time: {}ms", now.elapsed().as_millis());
@@ -178,12 +556,12 @@ aabbbbbaabbbaaaaaabbbbbababaaaaabbaaabba"#;
 
     #[test]
     fn test1() {
-        assert_eq!(solve1(INPUT), 2);
+        assert_eq!(solve1(INPUT).unwrap(), 2);
     }
 
     #[test]
     fn test2() {
-        assert_eq!(solve2(INPUT2), 12);
+        assert_eq!(solve2(INPUT2).unwrap(), 12);
     }
 
     #[test]
@@ -194,7 +572,7 @@ aabbbbbaabbbaaaaaabbbbbababaaaaabbaaabba"#;
 
 aa
 "#
-        ), 1);
+        ).unwrap(), 1);
     }
 
     #[test]
@@ -207,6 +585,117 @@ aa
 
 aaabb
 "#
-        ), 1);
+        ).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_explain_match() {
+        let (rules, _) = parse(INPUT).unwrap();
+        match explain_message(&rules, "ababbb") {
+            Explanation::Match(Derivation::Rule { rule, children, .. }) => {
+                assert_eq!(rule, "0");
+                assert_eq!(children.len(), 3);
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_explain_no_match() {
+        let (rules, _) = parse(INPUT).unwrap();
+        match explain_message(&rules, "aaabbb") {
+            Explanation::NoMatch { prefix, attempts } => {
+                assert_eq!(prefix, "aaab");
+                assert_eq!(
+                    attempts,
+                    vec![Attempt {
+                        path: vec![("0", 0), ("1", 0), ("3", 1), ("4", 0)],
+                        expected: Some("a")
+                    }]
+                );
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+
+        match explain_message(&rules, "aaaabbb") {
+            Explanation::NoMatch { prefix, attempts } => {
+                assert_eq!(prefix, "aaaabb");
+                assert_eq!(
+                    attempts,
+                    vec![Attempt {
+                        path: vec![("0", 0)],
+                        expected: None
+                    }]
+                );
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_explain_report() {
+        let report = explain(INPUT, false).unwrap();
+        assert!(report.contains("ababbb: match\n  0: 4 1 5\n    4: \"a\"\n    1: 3 2\n"));
+        assert!(report.contains("bababa: no match\n  longest matched prefix: \"\" (0 of 6)"));
+    }
+
+    #[test]
+    fn test_explain_looped() {
+        let report = explain(INPUT2, true).unwrap();
+        assert_eq!(report.matches(": match\n").count(), 12);
+    }
+
+    #[test]
+    fn test_enumerate() {
+        let (rules, _) = parse(INPUT).unwrap();
+        let mut generator = Generator::new(&rules);
+        let messages = generator.enumerate("0", 6);
+        assert_eq!(messages.len(), 8);
+        assert!(messages.iter().all(|m| matches(&rules, m)));
+
+        // Every 6 letter message over {a, b} that matches is enumerated
+        let brute = (0..64)
+            .map(|n: u32| (0..6).map(|i| if n >> (5 - i) & 1 == 1 { 'b' } else { 'a' }).collect())
+            .filter(|m: &String| matches(&rules, m))
+            .collect::<Vec<_>>();
+        assert_eq!(messages, brute);
+
+        assert!(generator.enumerate("0", 5).is_empty());
+    }
+
+    #[test]
+    fn test_sample_fuzz() {
+        let (mut rules, _) = parse(INPUT2).unwrap();
+        loop_rules(&mut rules);
+        let generator = Generator::new(&rules);
+        let mut rng = Rng::new(2020);
+        for _ in 0..50 {
+            let message = generator.sample("0", 60, &mut rng).unwrap();
+            assert!(message.len() <= 60);
+            assert!(matches(&rules, &message), "{}", message);
+        }
+        assert_eq!(generator.sample("0", 5, &mut rng), None);
+    }
+
+    #[test]
+    fn test_generate() {
+        let generated = generate(INPUT, 6, None).unwrap();
+        assert_eq!(solve1(&generated).unwrap(), 8);
+        let sampled = generate(INPUT, 6, Some((5, 7))).unwrap();
+        assert_eq!(sampled, generate(INPUT, 6, Some((5, 7))).unwrap());
+    }
+
+    #[test]
+    fn test_bad_rules() {
+        let err = solve1("0: 1\n1: \"\n\na").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Rule 1 refers to '\"', which is neither a rule nor a literal"
+        );
+        assert!(solve1("0: 2\n1: \"a\"\n\na").is_err());
+        assert!(solve1("0 1\n\na").is_err());
+
+        let err = solve1("1: \"a\"\n\na\n").unwrap_err();
+        assert_eq!(err.to_string(), "No rule 0 to match messages against");
     }
 }
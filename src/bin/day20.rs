@@ -6,12 +6,11 @@ use std::env;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Formatter;
+use std::fs;
 use std::io::{self, Read};
 use std::str::FromStr;
 
-const S: usize = 10;
-
-const MONSTER: &'static str = "                  # 
+const MONSTER: &str = "                  # 
 #    ##    ##    ###
  #  #  #  #  #  #   ";
 
@@ -26,23 +25,25 @@ enum Dir {
     Left = 3,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-enum Flipped {
-    Yes = 1,
-    No = 0,
+/// A symmetry of the square, i.e. an element of the dihedral group D4: turn
+/// clockwise `rotation` quarter turns, then mirror left to right if `flipped`.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Default)]
+struct Orientation {
+    rotation: i32,
+    flipped: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
 struct TileMeta {
     id: TileId,
-    rotation: i32,
-    flipped: bool,
+    orientation: Orientation,
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Default)]
 struct Tile {
     id: TileId,
-    borders: [u16; 4],
+    size: usize,
+    borders: [u64; 4],
     contents: Vec<Vec<char>>,
 }
 
@@ -52,114 +53,143 @@ impl fmt::Display for TileId {
     }
 }
 
-impl TileMeta {
-    fn lookup(&self, x: usize, y: usize) -> (usize, usize) {
-        let mut x = x;
-        let mut y = y;
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "rotation={} flipped={}", self.rotation, self.flipped)
+    }
+}
+
+impl Orientation {
+    fn new(rotation: i32, flipped: bool) -> Orientation {
+        Orientation {
+            rotation: rotation.rem_euclid(4),
+            flipped,
+        }
+    }
+
+    fn all() -> Vec<Orientation> {
+        [false, true]
+            .iter()
+            .flat_map(|flipped| (0..4).map(move |rotation| Orientation::new(rotation, *flipped)))
+            .collect()
+    }
+
+    /// `self` followed by `other`. Mirroring reverses the sense of any
+    /// rotation that comes after it.
+    fn then(&self, other: &Orientation) -> Orientation {
+        let rotation = if self.flipped {
+            -other.rotation
+        } else {
+            other.rotation
+        };
+        Orientation::new(self.rotation + rotation, self.flipped != other.flipped)
+    }
+
+    fn inverse(&self) -> Orientation {
         if self.flipped {
-            x = S - x - 1;
+            *self
+        } else {
+            Orientation::new(-self.rotation, false)
         }
+    }
 
+    /// Where the point `(x, y)` of a `width` x `height` grid ends up.
+    fn apply(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        let (mut x, mut y, mut width, mut height) = (x, y, width, height);
         for _ in 0..self.rotation {
-            // Rotate anti clockwise
-            let temp = S - x - 1;
-            x = y;
-            y = temp;
+            let temp = height - y - 1;
+            y = x;
+            x = temp;
+            std::mem::swap(&mut width, &mut height);
+        }
+        if self.flipped {
+            x = width - x - 1;
         }
         (x, y)
     }
 
-    fn border(&self, tile: &Tile, dir: Dir) -> u16 {
-        let mut dir = dir;
-        let mut flip_contents = false;
+    fn transform<T: Clone>(&self, grid: &[Vec<T>]) -> Vec<Vec<T>> {
+        let height = grid.len();
+        let width = grid.first().map_or(0, |x| x.len());
 
-        // Flipping happens after rotation
-        if self.flipped {
-            match dir {
-                Dir::Top | Dir::Bottom => {
-                    flip_contents = true;
-                }
-                Dir::Left => {
-                    dir = Dir::Right;
-                }
-                Dir::Right => {
-                    dir = Dir::Left;
-                }
+        let mut transformed = if self.rotation % 2 == 0 {
+            vec![Vec::with_capacity(width); height]
+        } else {
+            vec![Vec::with_capacity(height); width]
+        };
+        let mut cells = vec![];
+        for (y, row) in grid.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                cells.push((self.apply(x, y, width, height), cell));
             }
         }
 
-        let original_dir = dir.rotate(-self.rotation);
-
-        if original_dir as u8 / 2 != dir as u8 / 2 {
-            flip_contents = !flip_contents;
+        cells.sort_by_key(|((x, y), _)| (*y, *x));
+        for ((_, y), cell) in cells {
+            transformed[y].push(cell.clone());
         }
+        transformed
+    }
+}
 
-        let border = tile.border(original_dir);
-        if flip_contents {
-            Tile::invert(border)
-        } else {
-            border
-        }
+impl TileMeta {
+    fn lookup(&self, x: usize, y: usize, size: usize) -> (usize, usize) {
+        self.orientation.inverse().apply(x, y, size, size)
     }
 
-    fn transform(id: TileId, dir1: Dir, dir2: Dir, flip: Flipped) -> TileMeta {
-        let mut rotation = Dir::rotation(&dir2, &dir1);
-        let mut flipped = false;
+    /// The border on side `dir` once the tile is oriented, read left to
+    /// right or top to bottom like the borders of `Tile`. The ends of that
+    /// edge are looked up in the original tile to find which of its borders
+    /// it is, and whether it now reads backwards.
+    fn border(&self, tile: &Tile, dir: Dir) -> u64 {
+        let last = tile.size - 1;
+        let (start, end) = match dir {
+            Dir::Top => ((0, 0), (last, 0)),
+            Dir::Right => ((last, 0), (last, last)),
+            Dir::Bottom => ((0, last), (last, last)),
+            Dir::Left => ((0, 0), (0, last)),
+        };
+        let (x0, y0) = self.lookup(start.0, start.1, tile.size);
+        let (x1, y1) = self.lookup(end.0, end.1, tile.size);
 
-        let contents_flipped = if dir1 as u8 / 2 != dir2 as u8 / 2 {
-            Flipped::Yes
+        let (original, reversed) = if y0 == y1 {
+            (if y0 == 0 { Dir::Top } else { Dir::Bottom }, x1 < x0)
         } else {
-            Flipped::No
+            (if x0 == 0 { Dir::Left } else { Dir::Right }, y1 < y0)
         };
-
-        if flip != contents_flipped {
-            if dir1 == Dir::Left || dir1 == Dir::Right {
-                rotation += 2;
-            }
-            flipped = true;
-        }
-
-        TileMeta {
-            id,
-            rotation: rotation % 4,
-            flipped,
+        if reversed {
+            Tile::invert(tile.border(original), tile.size)
+        } else {
+            tile.border(original)
         }
     }
 }
 
 impl Tile {
-    fn encode(line: &[char]) -> u16 {
-        assert_eq!(line.len(), 10);
+    fn encode(line: &[char]) -> u64 {
+        assert!(line.len() <= 64);
         line.iter()
             .map(|x| match x {
-                '.' => 0 as u16,
-                '#' => 1 as u16,
+                '.' => 0,
+                '#' => 1,
                 _ => unreachable!(),
             })
             .fold(0, |a, x| a * 2 + x)
     }
 
-    fn invert(x: u16) -> u16 {
+    fn invert(x: u64, size: usize) -> u64 {
         let mut inverted = 0;
         let mut x = x;
-        for _ in 0..10 {
+        for _ in 0..size {
             inverted = inverted * 2 + (x & 1);
             x /= 2;
         }
         inverted
     }
 
-    fn border(&self, dir: Dir) -> u16 {
+    fn border(&self, dir: Dir) -> u64 {
         self.borders[dir as usize]
     }
-
-    fn to_meta(&self) -> TileMeta {
-        TileMeta {
-            id: self.id,
-            rotation: 0,
-            flipped: false,
-        }
-    }
 }
 
 impl FromStr for Tile {
@@ -171,6 +201,11 @@ impl FromStr for Tile {
         let id = TileId(id_line[5..id_line.len() - 1].parse::<u64>()?);
         let contents = lines.map(|x| x.chars().collect_vec()).collect::<Vec<_>>();
 
+        let size = contents.len();
+        if size > 64 || contents.iter().any(|x| x.len() != size) {
+            return Err(format!("Tile {} must be square and at most 64 wide", id).into());
+        }
+
         let mut borders = [0; 4];
         borders[Dir::Top as usize] = Tile::encode(&contents[0]);
         borders[Dir::Bottom as usize] = Tile::encode(&contents[contents.len() - 1]);
@@ -181,6 +216,7 @@ impl FromStr for Tile {
 
         Ok(Tile {
             id,
+            size,
             contents,
             borders,
         })
@@ -194,7 +230,7 @@ impl fmt::Debug for Tile {
         f.write_fmt(format_args!("       {:5}      \n", self.border(Dir::Top)))?;
         f.write_fmt(format_args!(
             "      !{:5}      \n",
-            Tile::invert(self.border(Dir::Top))
+            Tile::invert(self.border(Dir::Top), self.size)
         ))?;
         f.write_fmt(format_args!(
             " {:5}       {:5}\n",
@@ -203,8 +239,8 @@ impl fmt::Debug for Tile {
         ))?;
         f.write_fmt(format_args!(
             "!{:5}      !{:5}\n",
-            Tile::invert(self.border(Dir::Left)),
-            Tile::invert(self.border(Dir::Right))
+            Tile::invert(self.border(Dir::Left), self.size),
+            Tile::invert(self.border(Dir::Right), self.size)
         ))?;
         f.write_fmt(format_args!(
             "       {:5}      \n",
@@ -212,7 +248,7 @@ impl fmt::Debug for Tile {
         ))?;
         f.write_fmt(format_args!(
             "      !{:5}      \n",
-            Tile::invert(self.border(Dir::Bottom))
+            Tile::invert(self.border(Dir::Bottom), self.size)
         ))?;
 
         f.write_str("\n")
@@ -233,7 +269,7 @@ impl fmt::Display for Tile {
 
 impl Dir {
     fn from(val: i32) -> Dir {
-        match ((val % 4) + 4) % 4 {
+        match val.rem_euclid(4) {
             0 => Dir::Top,
             1 => Dir::Right,
             2 => Dir::Bottom,
@@ -241,80 +277,143 @@ impl Dir {
             _ => unreachable!(),
         }
     }
-
-    fn rotate(&self, clockwise: i32) -> Dir {
-        Dir::from(*self as i32 + clockwise)
-    }
-
-    pub fn rotation(d1: &Dir, d2: &Dir) -> i32 {
-        ((*d2 as i32 - *d1 as i32) %4 + 4) % 4
-    }
 }
 
 fn parse_tiles(buffer: &str) -> Result<HashMap<TileId, Tile>, Box<dyn Error>> {
-    Ok(buffer
+    let tiles = buffer
         .trim()
         .split("\n\n")
-        .map(|x| x.parse::<Tile>().unwrap())
+        .map(|x| x.parse::<Tile>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if tiles.iter().map(|x| x.size).dedup().count() > 1 {
+        return Err("All tiles must be the same size".into());
+    }
+
+    Ok(tiles
+        .into_iter()
         .map(|x| (x.id, x))
         .collect::<HashMap<_, _>>())
 }
 
-fn identify_corners(tiles: &HashMap<TileId, Tile>) -> Vec<TileId> {
-    let mut border_dict: HashMap<u16, Vec<&Tile>> = HashMap::new();
-    for (_id, tile) in tiles.iter() {
+/// Number of borders of each tile that don't match any other tile, counting
+/// both readings of a border: so 4 for corners and 2 for edges.
+fn unshared_borders(tiles: &HashMap<TileId, Tile>) -> HashMap<TileId, usize> {
+    let mut border_dict: HashMap<u64, HashSet<TileId>> = HashMap::new();
+    for (id, tile) in tiles.iter() {
         for border in &tile.borders {
-            let entry = border_dict.entry(*border).or_insert(vec![]);
-            entry.push(tile);
-            let flipped_entry = border_dict.entry(Tile::invert(*border)).or_insert(vec![]);
-            flipped_entry.push(tile);
+            let entry = border_dict.entry(*border).or_default();
+            entry.insert(*id);
+            let flipped_entry = border_dict
+                .entry(Tile::invert(*border, tile.size))
+                .or_default();
+            flipped_entry.insert(*id);
         }
     }
 
     let mut tile_unshared_count: HashMap<TileId, usize> = HashMap::new();
-    for tiles in border_dict.values() {
-        if tiles.len() == 1 {
-            *tile_unshared_count.entry(tiles[0].id).or_insert(0) += 1;
+    for (id, tile) in tiles.iter() {
+        for border in &tile.borders {
+            for border in &[*border, Tile::invert(*border, tile.size)] {
+                if border_dict[border].len() == 1 {
+                    *tile_unshared_count.entry(*id).or_insert(0) += 1;
+                }
+            }
         }
     }
 
     tile_unshared_count
+}
+
+fn identify_corners(tiles: &HashMap<TileId, Tile>) -> Vec<TileId> {
+    unshared_borders(tiles)
         .iter()
         .filter(|(_x, y)| **y == 4)
         .map(|(x, _y)| *x)
         .collect::<Vec<_>>()
 }
 
-fn print_grid(grid: &Vec<Vec<TileMeta>>, tiles: &HashMap<TileId, Tile>) {
+/// Sides of the rectangle the tiles form, shorter side first: the corner
+/// and edge tiles make up the perimeter, and all of them the area.
+fn arrangement(tiles: &HashMap<TileId, Tile>) -> Result<(usize, usize), Box<dyn Error>> {
+    let unshared = unshared_borders(tiles);
+    let corners = unshared.values().filter(|x| **x == 4).count();
+    let edges = unshared.values().filter(|x| **x == 2).count();
+    if corners != 4 || edges % 2 != 0 {
+        return Err(format!("Found {} corners and {} edges", corners, edges).into());
+    }
+
+    let half_perimeter = edges / 2 + 4;
+    let area = tiles.len();
+    let discriminant = (half_perimeter * half_perimeter)
+        .checked_sub(4 * area)
+        .ok_or("Tiles don't form a rectangle")?;
+    let root = (discriminant as f64).sqrt() as usize;
+    if root * root != discriminant || !(half_perimeter - root).is_multiple_of(2) {
+        return Err("Tiles don't form a rectangle".into());
+    }
+
+    Ok(((half_perimeter - root) / 2, (half_perimeter + root) / 2))
+}
+
+fn make_picture(grid: &[Vec<TileMeta>], tiles: &HashMap<TileId, Tile>) -> Vec<Vec<char>> {
+    let mut pic = vec![];
+
     for row in grid {
-        for y in 0..10 {
+        let size = tiles[&row[0].id].size;
+        for y in 1..(size - 1) {
+            let mut output = vec![];
             for col in row {
                 if col.id.0 != 0 {
-                    for x in 0..10 {
-                        let (x, y) = col.lookup(x, y);
-                        print!("{}", tiles[&col.id].contents[y][x]);
+                    for x in 1..(size - 1) {
+                        let (x, y) = col.lookup(x, y, size);
+                        output.push(tiles[&col.id].contents[y][x]);
                     }
-                    print!(" ");
                 }
             }
-            println!();
+            pic.push(output);
         }
-        println!();
     }
+
+    pic
 }
 
-fn make_picture(grid: &Vec<Vec<TileMeta>>, tiles: &HashMap<TileId, Tile>) -> Vec<Vec<char>> {
-    let mut pic = vec![];
+/// The whole assembly turned by `orientation`: tiles move to their new
+/// places, and each one turns along with it.
+fn orient_grid(grid: &[Vec<TileMeta>], orientation: &Orientation) -> Vec<Vec<TileMeta>> {
+    orientation
+        .transform(grid)
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|meta| TileMeta {
+                    orientation: meta.orientation.then(orientation),
+                    ..meta
+                })
+                .collect()
+        })
+        .collect()
+}
 
-    for row in grid {
-        for y in 1..(S - 1) {
+/// Every tile in full, borders included, with a blank gutter between tiles.
+fn make_bordered_picture(grid: &[Vec<TileMeta>], tiles: &HashMap<TileId, Tile>) -> Vec<Vec<char>> {
+    let mut pic: Vec<Vec<char>> = vec![];
+
+    for (i, row) in grid.iter().enumerate() {
+        if i > 0 {
+            pic.push(vec![' '; pic[0].len()]);
+        }
+
+        let size = tiles[&row[0].id].size;
+        for y in 0..size {
             let mut output = vec![];
-            for col in row {
-                if col.id.0 != 0 {
-                    for x in 1..(S - 1) {
-                        let (x, y) = col.lookup(x, y);
-                        output.push(tiles[&col.id].contents[y][x]);
-                    }
+            for (j, col) in row.iter().enumerate() {
+                if j > 0 {
+                    output.push(' ');
+                }
+                for x in 0..size {
+                    let (x, y) = col.lookup(x, y, size);
+                    output.push(tiles[&col.id].contents[y][x]);
                 }
             }
             pic.push(output);
@@ -324,24 +423,103 @@ fn make_picture(grid: &Vec<Vec<TileMeta>>, tiles: &HashMap<TileId, Tile>) -> Vec
     pic
 }
 
+/// The assembled picture with every match of `pattern` marked `O`.
+fn render(
+    grid: &[Vec<TileMeta>],
+    tiles: &HashMap<TileId, Tile>,
+    pattern: &Pattern,
+    borders: bool,
+) -> Vec<Vec<char>> {
+    let mut pic = make_picture(grid, tiles);
+    let matches = find_pattern(&pic, pattern, true);
+    if !borders {
+        mark_matches(&mut pic, &matches);
+        return pic;
+    }
+
+    // Matches are found without borders, so shift them past the border and
+    // gutter of every tile to their left and above
+    let size = tiles[&grid[0][0].id].size;
+    let inner = size - 2;
+    let shift = |v: usize| (v / inner) * (size + 1) + v % inner + 1;
+
+    let mut pic = make_bordered_picture(grid, tiles);
+    for m in &matches {
+        for (x, y) in &m.cells {
+            pic[shift(*y)][shift(*x)] = 'O';
+        }
+    }
+    pic
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum ImageFormat {
+    Pbm,
+    Ppm,
+}
+
+impl FromStr for ImageFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit('.').next() {
+            Some("pbm") => Ok(ImageFormat::Pbm),
+            Some("ppm") => Ok(ImageFormat::Ppm),
+            _ => Err(format!("Can't tell the image format of {}, use .pbm or .ppm", s).into()),
+        }
+    }
+}
+
+/// Plain (ASCII) netpbm, so exports stay easy to diff: the bitmap is black
+/// for `#` and `O`, the pixmap colours water, waves, matches and gutters.
+fn export_image(pic: &[Vec<char>], format: ImageFormat) -> String {
+    let height = pic.len();
+    let width = pic.first().map_or(0, |x| x.len());
+
+    let mut out = match format {
+        ImageFormat::Pbm => format!("P1\n{} {}\n", width, height),
+        ImageFormat::Ppm => format!("P3\n{} {}\n255\n", width, height),
+    };
+
+    for row in pic {
+        let pixels = row
+            .iter()
+            .map(|ch| match (format, ch) {
+                (ImageFormat::Pbm, '#') | (ImageFormat::Pbm, 'O') => "1",
+                (ImageFormat::Pbm, _) => "0",
+                (ImageFormat::Ppm, '#') => "127 178 229",
+                (ImageFormat::Ppm, 'O') => "46 204 64",
+                (ImageFormat::Ppm, '.') => "30 58 95",
+                (ImageFormat::Ppm, _) => "0 0 0",
+            })
+            .collect::<Vec<_>>();
+        out.push_str(&pixels.join(" "));
+        out.push('\n');
+    }
+
+    out
+}
+
 // From rosetta code
-fn print_picture(pic: &Vec<Vec<char>>) {
+fn print_picture(pic: &[Vec<char>]) {
     for y in pic {
         for x in y {
-            print!("\x1b[{};{}m{}\x1b[0m",
-                   match x {
-                       'O' => 1,
-                       '#' => 5,
-                       _ => 0,
-                   },
-                   match x {
-                       'O' => 32,
-                       '#' => 34,
-                       _ => 0,
-                   },
-                   x);
+            print!(
+                "\x1b[{};{}m{}\x1b[0m",
+                match x {
+                    'O' => 1,
+                    '#' => 5,
+                    _ => 0,
+                },
+                match x {
+                    'O' => 32,
+                    '#' => 34,
+                    _ => 0,
+                },
+                x
+            );
         }
-        println!("");
+        println!();
     }
 }
 
@@ -350,177 +528,336 @@ fn solve1(buffer: &str) -> Result<u64, Box<dyn Error>> {
     Ok(identify_corners(&tiles).iter().map(|x| x.0).product())
 }
 
-fn monster_check(pic: &Vec<Vec<char>>, x: usize, y:usize) -> bool {
-    for (my, row) in MONSTER.lines().enumerate() {
-        for (mx, ch) in row.chars().enumerate() {
-            match ch {
-                '#' => if ch != pic[y + my][x + mx] { return false; }
-                _ => {}
-            } 
-        }
-    }
+/// Cells marked `#` must be `#` in the picture; anything else matches
+/// whatever is underneath.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Pattern {
+    rows: Vec<Vec<char>>,
+}
 
-    true
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct PatternMatch {
+    x: usize,
+    y: usize,
+    orientation: Orientation,
+    cells: Vec<(usize, usize)>,
 }
 
-fn monster_mark(pic: &mut Vec<Vec<char>>, x: usize, y: usize) {
-    for (my, row) in MONSTER.lines().enumerate() {
-        for (mx, ch) in row.chars().enumerate() {
-            if ch == '#' {
-                pic[y + my][x + mx] = 'O';
-            } 
+impl FromStr for Pattern {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines = s.trim_end_matches('\n').lines().collect::<Vec<_>>();
+        let width = lines.iter().map(|x| x.chars().count()).max().unwrap_or(0);
+        let rows = lines
+            .iter()
+            .map(|x| x.chars().pad_using(width, |_| ' ').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        if !rows.iter().flatten().any(|x| *x == '#') {
+            return Err("Pattern has no # cells".into());
         }
+
+        Ok(Pattern { rows })
     }
 }
 
-fn monster_hunt(pic: &mut Vec<Vec<char>>) -> usize {
-    let pic_height = pic.len();
-    let pic_width = pic[0].len();
-
-    let monster_height = MONSTER.lines().count();
-    let monster_width: usize = MONSTER.lines().next().unwrap().len();
-
-    let mut count = 0;
-    for y in 0..(pic_height - monster_height) {
-        for x in 0..(pic_width - monster_width) {
-            if monster_check(pic, x, y) {
-                count += 1;
-                monster_mark(pic, x, y);
+impl Pattern {
+    fn cells(&self) -> Vec<(usize, usize)> {
+        let mut cells = vec![];
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, ch) in row.iter().enumerate() {
+                if *ch == '#' {
+                    cells.push((x, y));
+                }
             }
         }
+        cells
     }
 
-    count
+    /// The pattern in each orientation that looks different from the others.
+    fn orientations(&self) -> Vec<(Orientation, Pattern)> {
+        let mut orientations: Vec<(Orientation, Pattern)> = vec![];
+        for orientation in Orientation::all() {
+            let rows = orientation.transform(&self.rows);
+            if !orientations.iter().any(|x| x.1.rows == rows) {
+                orientations.push((orientation, Pattern { rows }));
+            }
+        }
+        orientations
+    }
 }
 
-fn rotate_pic(pic: &Vec<Vec<char>>) -> Vec<Vec<char>> {
+/// Every placement of the pattern in any orientation, scanning each
+/// orientation top to bottom, left to right. Without `overlapping`, a match
+/// that reuses a cell of an earlier one is skipped.
+fn find_pattern(pic: &[Vec<char>], pattern: &Pattern, overlapping: bool) -> Vec<PatternMatch> {
     let pic_height = pic.len();
-    let pic_width = pic[0].len();
+    let pic_width = pic.first().map_or(0, |x| x.len());
+
+    let mut claimed = HashSet::new();
+    let mut matches = vec![];
+    for (orientation, oriented) in pattern.orientations() {
+        let height = oriented.rows.len();
+        let width = oriented.rows[0].len();
+        if height > pic_height || width > pic_width {
+            continue;
+        }
 
-    // height & width are interchanged
-    let mut new_pic: Vec<Vec<char>> = vec![vec![' '; pic_height]; pic_width];
+        let cells = oriented.cells();
+        for y in 0..=(pic_height - height) {
+            for x in 0..=(pic_width - width) {
+                if !cells.iter().all(|(cx, cy)| pic[y + cy][x + cx] == '#') {
+                    continue;
+                }
 
-    for (y, col) in pic.iter().enumerate() {
-        for (x, ch) in col.iter().enumerate() {
-            let new_x = pic_height - y - 1;
-            let new_y = x;
-            new_pic[new_y][new_x] = *ch;
+                let cells = cells
+                    .iter()
+                    .map(|(cx, cy)| (x + cx, y + cy))
+                    .collect::<Vec<_>>();
+                if !overlapping && cells.iter().any(|x| claimed.contains(x)) {
+                    continue;
+                }
+                claimed.extend(cells.iter().copied());
+
+                matches.push(PatternMatch {
+                    x,
+                    y,
+                    orientation,
+                    cells,
+                });
+            }
         }
     }
 
-    new_pic
+    matches
 }
 
-fn flip_pic(pic: &Vec<Vec<char>>) -> Vec<Vec<char>> {
-    let pic_height = pic.len();
-    let pic_width = pic[0].len();
-
-    // height & width are interchanged
-    let mut new_pic: Vec<Vec<char>> = vec![vec![' '; pic_width]; pic_height];
-
-    for (y, col) in pic.iter().enumerate() {
-        for (x, ch) in col.iter().enumerate() {
-            let new_x = pic_width - x - 1;
-            let new_y = y;
-            new_pic[new_y][new_x] = *ch;
+fn mark_matches(pic: &mut [Vec<char>], matches: &[PatternMatch]) {
+    for m in matches {
+        for (x, y) in &m.cells {
+            pic[*y][*x] = 'O';
         }
     }
+}
 
-    new_pic
+/// A tile in one of its 8 orientations, with the borders it then shows.
+struct Placement {
+    meta: TileMeta,
+    borders: [u64; 4],
 }
 
-fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
-    let tiles = parse_tiles(buffer)?;
-    let mut corners = identify_corners(&tiles);
-    corners.sort();
+/// Depth first search over placements, filling the grid row by row.
+struct Assembler {
+    placements: Vec<Placement>,
+    by_left: HashMap<u64, Vec<usize>>,
+    by_top: HashMap<u64, Vec<usize>>,
+    starts: Vec<usize>,
+    width: usize,
+    grid: Vec<usize>,
+    used: HashSet<TileId>,
+    placed: usize,
+    budget: usize,
+}
 
-    let mut borders: HashMap<u16, Vec<(TileId, Dir, Flipped)>> = HashMap::new();
-    for (id, tile) in tiles.iter() {
-        for (i, border) in tile.borders.iter().enumerate() {
-            let entry = borders.entry(*border).or_insert(vec![]);
-            entry.push((*id, Dir::from(i as i32), Flipped::No));
-            let flipped_entry = borders.entry(Tile::invert(*border)).or_insert(vec![]);
-            flipped_entry.push((*id, Dir::from(i as i32), Flipped::Yes));
+impl Assembler {
+    fn new(tiles: &HashMap<TileId, Tile>) -> Assembler {
+        // Likely corners go first, so well formed puzzles finish right away
+        let unshared = unshared_borders(tiles);
+        let ids = tiles
+            .keys()
+            .copied()
+            .sorted_by_key(|id| (std::cmp::Reverse(unshared.get(id).copied()), *id))
+            .collect::<Vec<_>>();
+
+        let mut placements = vec![];
+        for id in &ids {
+            for orientation in Orientation::all() {
+                let meta = TileMeta {
+                    id: *id,
+                    orientation,
+                };
+                let mut borders = [0; 4];
+                for (i, border) in borders.iter_mut().enumerate() {
+                    *border = meta.border(&tiles[id], Dir::from(i as i32));
+                }
+                placements.push(Placement { meta, borders });
+            }
         }
-    }
 
-    let mut top_left = tiles[&corners[1]].to_meta(); // for testing
-    let mut corner_dirs = HashSet::new();
-    for (i, border) in tiles[&corners[1]].borders.iter().enumerate() {
-        if borders[border].len() > 1 {
-            corner_dirs.insert(Dir::from(i as i32));
+        let mut by_left: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut by_top: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (i, placement) in placements.iter().enumerate() {
+            by_left
+                .entry(placement.borders[Dir::Left as usize])
+                .or_default()
+                .push(i);
+            by_top
+                .entry(placement.borders[Dir::Top as usize])
+                .or_default()
+                .push(i);
+        }
+
+        Assembler {
+            starts: (0..placements.len()).collect(),
+            placements,
+            by_left,
+            by_top,
+            width: 0,
+            grid: vec![],
+            used: HashSet::new(),
+            placed: 0,
+            budget: 0,
         }
     }
-    let mut desired_dirs = HashSet::new();
-    desired_dirs.insert(Dir::Right);
-    desired_dirs.insert(Dir::Bottom);
 
-    let mut corner_rotation = 0;
-    while corner_dirs != desired_dirs {
-        corner_rotation += 1;
-        corner_dirs = corner_dirs.into_iter()
-            .map(|x| x.rotate(1))
-            .collect::<HashSet<_>>();
+    fn border(&self, pos: usize, dir: Dir) -> u64 {
+        self.placements[self.grid[pos]].borders[dir as usize]
     }
-    top_left.rotation = corner_rotation;
 
-    let total = tiles.len();
-    let side = ((total as f64).sqrt()) as usize;
+    /// Extends the grid to `total` tiles, giving up once `budget`
+    /// placements have been tried.
+    fn fill(&mut self, total: usize) -> bool {
+        let pos = self.grid.len();
+        if pos == total {
+            return true;
+        }
+        if self.placed == self.budget {
+            return false;
+        }
 
-    let mut grid: Vec<Vec<TileMeta>> = vec![vec![Default::default(); side]; side];
+        let (y, x) = (pos / self.width, pos % self.width);
+        let candidates = if pos == 0 {
+            self.starts.clone()
+        } else if x > 0 {
+            let constraint = self.border(pos - 1, Dir::Right);
+            self.by_left.get(&constraint).cloned().unwrap_or_default()
+        } else {
+            let constraint = self.border(pos - self.width, Dir::Bottom);
+            self.by_top.get(&constraint).cloned().unwrap_or_default()
+        };
 
-    grid[0][0] = top_left;
+        for candidate in candidates {
+            let placement = &self.placements[candidate];
+            if self.used.contains(&placement.meta.id) {
+                continue;
+            }
+            if y > 0
+                && x > 0
+                && placement.borders[Dir::Top as usize]
+                    != self.border(pos - self.width, Dir::Bottom)
+            {
+                continue;
+            }
 
-    // Fill the first row
-    for i in 1..side {
-        let prev = &grid[0][i - 1];
-        let constraint = prev.border(&tiles[&prev.id], Dir::Right);
-        let option = *&borders[&constraint]
-            .iter()
-            .filter(|x| x.0 != prev.id)
-            .next()
-            .unwrap();
+            self.placed += 1;
+            self.used.insert(placement.meta.id);
+            self.grid.push(candidate);
+            if self.fill(total) {
+                return true;
+            }
+            let candidate = self.grid.pop().unwrap();
+            self.used.remove(&self.placements[candidate].meta.id);
+            if self.placed == self.budget {
+                return false;
+            }
+        }
 
-        grid[0][i] = TileMeta::transform(option.0, Dir::Left, option.1, option.2);
+        false
     }
+}
 
-    // Fill the rest of the grid row by row
-    for y in 1..side {
-        for x in 0..side {
-            let prev = &grid[y - 1][x];
-            let constraint = prev.border(&tiles[&prev.id], Dir::Bottom);
-            let options = &borders[&constraint]
-                .iter()
-                .filter(|x| x.0 != prev.id)
-                .copied()
-                .collect::<Vec<_>>();
-            assert_eq!(options.len(), 1);
-            let option = options[0];
-            let transform = TileMeta::transform(option.0, Dir::Top, option.1, option.2);
-            grid[y][x] = transform;
-        }
-    }
+/// Placements tried for each width before moving on to the next, plenty for
+/// puzzles whose borders are mostly unique.
+const BUDGET: usize = 1_000_000;
 
-    println!();
-    print_grid(&grid, &tiles);
-    let mut pic = make_picture(&grid, &tiles);
+/// Lays the tiles out so that all neighbouring borders match, backtracking
+/// when several tiles share a border. The top left tile is the first one that
+/// works, preferring corners by id.
+fn assemble(tiles: &HashMap<TileId, Tile>) -> Result<Vec<Vec<TileMeta>>, Box<dyn Error>> {
+    assemble_with(tiles, false, BUDGET)
+}
 
-    for i in 0..8 {
-        let monsters = monster_hunt(&mut pic);
-        if monsters > 0 {
-            print_picture(&pic);
-            println!();
-            let roughness = pic.iter().flatten().filter(|x| **x == '#').count();
-            return Ok(roughness);
+/// Like `assemble`, but single row or column layouts are only tried when
+/// `strips` is set, and each width gets `budget` placements.
+fn assemble_with(
+    tiles: &HashMap<TileId, Tile>,
+    strips: bool,
+    budget: usize,
+) -> Result<Vec<Vec<TileMeta>>, Box<dyn Error>> {
+    let total = tiles.len();
+    // The counts can be confused by coincidentally shared borders, so the
+    // shape they suggest goes first and the others follow, squarest first
+    let suggested = match arrangement(tiles) {
+        Ok((short, long)) => vec![long, short],
+        Err(_) => vec![],
+    };
+    let others = (1..=total)
+        .rev()
+        .filter(|w| total.is_multiple_of(*w))
+        .sorted_by_key(|w| (*w).max(total / w));
+    let widths = suggested
+        .into_iter()
+        .chain(others)
+        .filter(|w| strips || total == 1 || (*w != 1 && *w != total))
+        .unique()
+        .collect::<Vec<_>>();
+
+    let mut assembler = Assembler::new(tiles);
+    assembler.budget = budget;
+    let mut gave_up = vec![];
+    for width in &widths {
+        assembler.width = *width;
+        assembler.placed = 0;
+        if assembler.fill(total) {
+            return Ok(assembler
+                .grid
+                .chunks(*width)
+                .map(|row| {
+                    row.iter()
+                        .map(|i| assembler.placements[*i].meta.clone())
+                        .collect()
+                })
+                .collect());
         }
-
-        pic = rotate_pic(&pic);
-        if i == 4 {
-            pic = flip_pic(&pic);
+        if assembler.placed == budget {
+            gave_up.push(*width);
         }
     }
 
-    Ok(0)
+    if !gave_up.is_empty() {
+        return Err(format!(
+            "Search gave up after {} placements without fitting the {} tiles (widths {:?})",
+            budget, total, gave_up
+        )
+        .into());
+    }
+    Err(format!(
+        "No arrangement of the {} tiles fits together (tried widths {:?})",
+        total, widths
+    )
+    .into())
+}
+
+fn solve2(buffer: &str, pattern: &Pattern) -> Result<usize, Box<dyn Error>> {
+    let tiles = parse_tiles(buffer)?;
+    let grid = assemble(&tiles)?;
+    let pic = render(&grid, &tiles, pattern, false);
+
+    Ok(pic.iter().flatten().filter(|x| **x == '#').count())
+}
+
+fn search(buffer: &str, pattern: &Pattern, overlapping: bool) -> Result<String, Box<dyn Error>> {
+    let tiles = parse_tiles(buffer)?;
+    let grid = assemble(&tiles)?;
+    let pic = make_picture(&grid, &tiles);
+
+    let matches = find_pattern(&pic, pattern, overlapping);
+    let mut out = format!("{} matches\n", matches.len());
+    for m in matches {
+        out.push_str(&format!("x={} y={} {}\n", m.x, m.y, m.orientation));
+    }
+    Ok(out)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -530,8 +867,44 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
+    let pattern = |arg: Option<&String>| match arg {
+        Some(path) => fs::read_to_string(path)?.parse::<Pattern>(),
+        None => MONSTER.parse::<Pattern>(),
+    };
+
     if args.len() > 1 && args[1] == "2" {
-        println!("{}", solve2(&buffer)?);
+        println!("{}", solve2(&buffer, &pattern(args.get(2))?)?);
+    } else if args.len() > 1 && args[1] == "search" {
+        let overlapping = args.len() > 3 && args[3] == "overlapping";
+        print!("{}", search(&buffer, &pattern(args.get(2))?, overlapping)?);
+    } else if args.len() > 1 && (args[1] == "show" || args[1] == "export") {
+        // show [borders] [upright] [strips] [pattern]
+        // export <file.pbm|file.ppm> [borders] [upright] [strips] [pattern]
+        let skip = if args[1] == "show" { 2 } else { 3 };
+        let rest = args.get(skip..).unwrap_or(&[]);
+        let borders = rest.iter().any(|x| x == "borders");
+        let upright = rest.iter().any(|x| x == "upright");
+        let strips = rest.iter().any(|x| x == "strips");
+        let flags = ["borders", "upright", "strips"];
+        let pattern = pattern(rest.iter().find(|x| !flags.contains(&x.as_str())))?;
+
+        let tiles = parse_tiles(&buffer)?;
+        let mut grid = assemble_with(&tiles, strips, BUDGET)?;
+        if upright {
+            // Turn the whole assembly so the first match reads as written
+            let matches = find_pattern(&make_picture(&grid, &tiles), &pattern, true);
+            if let Some(m) = matches.first() {
+                grid = orient_grid(&grid, &m.orientation.inverse());
+            }
+        }
+        let pic = render(&grid, &tiles, &pattern, borders);
+
+        if args[1] == "show" {
+            print_picture(&pic);
+        } else {
+            let path = args.get(2).ok_or("Missing output file")?;
+            fs::write(path, export_image(&pic, path.parse::<ImageFormat>()?))?;
+        }
     } else {
         println!("{}", solve1(&buffer)?);
     }
@@ -543,8 +916,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use aoc2020::rng::Rng;
 
-    const INPUT: &'static str = "\
+    const INPUT: &str = "\
 Tile 2311:
 ..##.#..#.
 ##..#.....
@@ -653,102 +1027,232 @@ Tile 3079:
 ..#.......
 ..#.###...";
 
+    /// Cuts a random picture into `rows` x `cols` tiles of `size` pixels in
+    /// random orientations; if `unique`, retries until no two borders look
+    /// alike. Returns the puzzle input, the corner ids, and the picture the tile
+    /// interiors should assemble back into.
+    fn jigsaw(
+        rows: usize,
+        cols: usize,
+        size: usize,
+        seed: u64,
+        unique: bool,
+    ) -> (String, Vec<u64>, Vec<Vec<char>>) {
+        let mut rng = Rng::new(seed);
+
+        // Neighbouring tiles overlap by one line of pixels: their shared border
+        let step = size - 1;
+        let (height, width) = (rows * step + 1, cols * step + 1);
+        let pixels = loop {
+            let pixels = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| if rng.below(2) == 0 { '#' } else { '.' })
+                        .collect()
+                })
+                .collect::<Vec<Vec<char>>>();
+
+            let mut seen = HashSet::new();
+            let mut distinct = true;
+            for y in 0..=rows {
+                for x in 0..cols {
+                    let line = (0..size).map(|i| pixels[y * step][x * step + i]);
+                    distinct &= seen.insert(line.clone().collect::<String>());
+                    distinct &= seen.insert(line.rev().collect::<String>());
+                }
+            }
+            for y in 0..rows {
+                for x in 0..=cols {
+                    let line = (0..size).map(|i| pixels[y * step + i][x * step]);
+                    distinct &= seen.insert(line.clone().collect::<String>());
+                    distinct &= seen.insert(line.rev().collect::<String>());
+                }
+            }
+
+            if distinct || !unique {
+                break pixels;
+            }
+        };
+
+        let mut input = vec![];
+        for y in 0..rows {
+            for x in 0..cols {
+                let contents = pixels[y * step..y * step + size]
+                    .iter()
+                    .map(|row| row[x * step..x * step + size].to_vec())
+                    .collect::<Vec<_>>();
+                let contents = Orientation::all()[rng.below(8)].transform(&contents);
+
+                let mut lines = contents.iter().map(|row| row.iter().collect::<String>());
+                input.push(format!(
+                    "Tile {}:\n{}",
+                    1000 + y * cols + x,
+                    lines.join("\n")
+                ));
+            }
+        }
+
+        let corners = vec![0, cols - 1, (rows - 1) * cols, rows * cols - 1]
+            .into_iter()
+            .map(|i| 1000 + i as u64)
+            .collect();
+
+        let picture = pixels
+            .iter()
+            .enumerate()
+            .filter(|(y, _)| y % step != 0)
+            .map(|(_, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(x, _)| x % step != 0)
+                    .map(|(_, ch)| *ch)
+                    .collect()
+            })
+            .collect();
+
+        (input.join("\n\n"), corners, picture)
+    }
+
+    fn assert_same_picture(actual: &[Vec<char>], expected: &[Vec<char>]) {
+        if Orientation::all()
+            .iter()
+            .any(|x| actual == x.transform(expected).as_slice())
+        {
+            return;
+        }
+        panic!("Picture doesn't match in any orientation");
+    }
+
+    fn assert_fits(grid: &[Vec<TileMeta>], tiles: &HashMap<TileId, Tile>) {
+        let ids = grid.iter().flatten().map(|x| x.id).collect::<HashSet<_>>();
+        assert_eq!(ids.len(), tiles.len());
+
+        let border = |meta: &TileMeta, dir| meta.border(&tiles[&meta.id], dir);
+        for (y, row) in grid.iter().enumerate() {
+            for (x, meta) in row.iter().enumerate() {
+                if x > 0 {
+                    assert_eq!(border(&row[x - 1], Dir::Right), border(meta, Dir::Left));
+                }
+                if y > 0 {
+                    assert_eq!(border(&grid[y - 1][x], Dir::Bottom), border(meta, Dir::Top));
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_encode() {
         for test in &["##.#.#####", ".#..######", "..#.......", "######...."] {
             let mut num = test.to_string();
             num = num.replace("#", "1");
             num = num.replace(".", "0");
-            let num = u16::from_str_radix(&num, 2).unwrap();
+            let num = u64::from_str_radix(&num, 2).unwrap();
             assert_eq!(num, Tile::encode(&test.chars().collect::<Vec<_>>()));
         }
     }
 
     #[test]
     fn test_invert() {
-        let test = u16::from_str_radix("0001010010", 2).unwrap();
-        let inverted = u16::from_str_radix("0100101000", 2).unwrap();
-        let x = Tile::invert(test);
+        let test = u64::from_str_radix("0001010010", 2).unwrap();
+        let inverted = u64::from_str_radix("0100101000", 2).unwrap();
+        let x = Tile::invert(test, 10);
         assert_eq!(inverted, x);
     }
 
     #[test]
     fn test_lookup() {
         let meta = TileMeta {
-            flipped: false,
-            rotation: 2,
+            orientation: Orientation::new(2, false),
             ..Default::default()
         };
-        assert_eq!(meta.lookup(9, 9), (0, 0));
+        assert_eq!(meta.lookup(9, 9, 10), (0, 0));
 
         let meta = TileMeta {
-            flipped: false,
-            rotation: 1,
+            orientation: Orientation::new(1, false),
             ..Default::default()
         };
-        assert_eq!(meta.lookup(1, 2), (2, 8));
+        assert_eq!(meta.lookup(1, 2, 10), (2, 8));
 
         let meta = TileMeta {
-            flipped: true,
-            rotation: 2,
+            orientation: Orientation::new(2, true),
             ..Default::default()
         };
-        assert_eq!(meta.lookup(1, 2), (1, 7));
+        assert_eq!(meta.lookup(1, 2, 10), (1, 7));
     }
 
     #[test]
     fn test_dir_lookup() {
         let tile = Tile {
+            size: 10,
             borders: [1, 2, 3, 4],
             ..Default::default()
         };
         let mut meta = TileMeta {
-            flipped: false,
-            rotation: 1,
+            orientation: Orientation::new(1, false),
             ..Default::default()
         };
         assert_eq!(
             meta.border(&tile, Dir::Top),
-            Tile::invert(tile.border(Dir::Left))
+            Tile::invert(tile.border(Dir::Left), 10)
         );
 
-        meta.rotation = 2;
+        meta.orientation.rotation = 2;
         assert_eq!(
             meta.border(&tile, Dir::Top),
-            Tile::invert(tile.border(Dir::Bottom))
+            Tile::invert(tile.border(Dir::Bottom), 10)
         );
 
-        meta.rotation = 3;
+        meta.orientation.rotation = 3;
         assert_eq!(meta.border(&tile, Dir::Top), tile.border(Dir::Right));
     }
 
     #[test]
     fn test_flipped_dir_lookup() {
         let tile = Tile {
+            size: 10,
             borders: [1, 2, 3, 4],
             ..Default::default()
         };
         let mut meta = TileMeta {
-            flipped: true,
-            rotation: 1,
+            orientation: Orientation::new(1, true),
             ..Default::default()
         };
         assert_eq!(meta.border(&tile, Dir::Top), tile.border(Dir::Left));
 
-        meta.rotation = 2;
+        meta.orientation.rotation = 2;
         assert_eq!(meta.border(&tile, Dir::Top), tile.border(Dir::Bottom));
         assert_eq!(
             meta.border(&tile, Dir::Right),
-            Tile::invert(tile.border(Dir::Right))
+            Tile::invert(tile.border(Dir::Right), 10)
         );
 
-        meta.rotation = 3;
+        meta.orientation.rotation = 3;
         assert_eq!(
             meta.border(&tile, Dir::Top),
-            Tile::invert(tile.border(Dir::Right))
+            Tile::invert(tile.border(Dir::Right), 10)
         );
     }
 
+    #[test]
+    fn test_border_matches_transform() {
+        let tiles = parse_tiles(INPUT).unwrap();
+        let tile = &tiles[&TileId(2311)];
+        for orientation in Orientation::all() {
+            let meta = TileMeta {
+                id: tile.id,
+                orientation,
+            };
+            let turned = orientation.transform(&tile.contents);
+            let left = turned.iter().map(|x| x[0]).collect::<Vec<_>>();
+            let right = turned.iter().map(|x| x[x.len() - 1]).collect::<Vec<_>>();
+
+            assert_eq!(meta.border(tile, Dir::Top), Tile::encode(&turned[0]));
+            assert_eq!(meta.border(tile, Dir::Right), Tile::encode(&right));
+            assert_eq!(meta.border(tile, Dir::Bottom), Tile::encode(&turned[9]));
+            assert_eq!(meta.border(tile, Dir::Left), Tile::encode(&left));
+        }
+    }
+
     #[test]
     fn test1() {
         assert_eq!(solve1(INPUT).unwrap(), 20899048083289);
@@ -756,6 +1260,245 @@ Tile 3079:
 
     #[test]
     fn test2() {
-        dbg!(solve2(INPUT));
+        assert_eq!(solve2(INPUT, &MONSTER.parse().unwrap()).unwrap(), 273);
+    }
+
+    #[test]
+    fn test_arrangement() {
+        let tiles = parse_tiles(INPUT).unwrap();
+        assert_eq!(arrangement(&tiles).unwrap(), (3, 3));
+
+        let (input, _, _) = jigsaw(3, 5, 10, 7, true);
+        let tiles = parse_tiles(&input).unwrap();
+        assert_eq!(arrangement(&tiles).unwrap(), (3, 5));
+    }
+
+    #[test]
+    fn test_rectangle() {
+        let (input, corners, picture) = jigsaw(3, 5, 10, 11, true);
+        assert_eq!(solve1(&input).unwrap(), corners.iter().product());
+
+        let tiles = parse_tiles(&input).unwrap();
+        let grid = assemble(&tiles).unwrap();
+        assert_eq!(grid.len() * grid[0].len(), 15);
+        assert_same_picture(&make_picture(&grid, &tiles), &picture);
+    }
+
+    #[test]
+    fn test_large_tiles() {
+        let (input, corners, picture) = jigsaw(4, 4, 12, 13, true);
+        assert_eq!(solve1(&input).unwrap(), corners.iter().product());
+
+        let tiles = parse_tiles(&input).unwrap();
+        assert!(tiles.values().all(|x| x.size == 12));
+        let grid = assemble(&tiles).unwrap();
+        assert_same_picture(&make_picture(&grid, &tiles), &picture);
+    }
+
+    #[test]
+    fn test_not_square() {
+        assert!(parse_tiles("Tile 1:\n#.#\n...").is_err());
+    }
+
+    #[test]
+    fn test_ambiguous_borders() {
+        // 5 pixel borders only have 32 values, so plenty of tiles share them
+        for seed in 0..60 {
+            let (input, _, _) = jigsaw(4, 4, 5, seed, false);
+            let tiles = parse_tiles(&input).unwrap();
+            let grid = assemble(&tiles).unwrap();
+            assert_eq!((grid.len(), grid[0].len()), (4, 4), "seed {}", seed);
+            assert_fits(&grid, &tiles);
+        }
+
+        let (input, _, _) = jigsaw(3, 5, 4, 19, false);
+        let tiles = parse_tiles(&input).unwrap();
+        let grid = assemble(&tiles).unwrap();
+        assert_fits(&grid, &tiles);
+    }
+
+    #[test]
+    fn test_strips() {
+        let (input, _, picture) = jigsaw(1, 4, 10, 23, true);
+        let tiles = parse_tiles(&input).unwrap();
+        let err = assemble(&tiles).unwrap_err().to_string();
+        assert!(err.starts_with("No arrangement of the 4 tiles"), "{}", err);
+
+        let grid = assemble_with(&tiles, true, BUDGET).unwrap();
+        assert_eq!(grid.len() * grid[0].len(), 4);
+        assert_same_picture(&make_picture(&grid, &tiles), &picture);
+    }
+
+    #[test]
+    fn test_budget() {
+        // Fewer placements than tiles can never finish, at any width
+        let (input, _, _) = jigsaw(4, 4, 5, 17, false);
+        let tiles = parse_tiles(&input).unwrap();
+        let err = assemble_with(&tiles, false, 10).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Search gave up after 10 placements without fitting the 16 tiles (widths [4, 8, 2])"
+        );
+        assert!(assemble_with(&tiles, false, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_deterministic_corner() {
+        let tiles = parse_tiles(INPUT).unwrap();
+        let grid = assemble(&tiles).unwrap();
+        assert_fits(&grid, &tiles);
+        assert_eq!(grid[0][0].id, TileId(1171));
+    }
+
+    #[test]
+    fn test_no_arrangement() {
+        let input = INPUT.split("\n\n").skip(1).join("\n\n");
+        let tiles = parse_tiles(&input).unwrap();
+        let err = assemble(&tiles).unwrap_err().to_string();
+        assert!(err.starts_with("No arrangement of the 8 tiles"), "{}", err);
+    }
+
+    #[test]
+    fn test_find_pattern() {
+        let tiles = parse_tiles(INPUT).unwrap();
+        let pic = make_picture(&assemble(&tiles).unwrap(), &tiles);
+        let monster = MONSTER.parse::<Pattern>().unwrap();
+
+        let matches = find_pattern(&pic, &monster, false);
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|m| m.cells.len() == 15));
+        assert!(matches
+            .iter()
+            .all(|m| m.orientation == matches[0].orientation));
+    }
+
+    #[test]
+    fn test_pattern_at_edges() {
+        let pic = vec!["....".chars().collect(), "..##".chars().collect()];
+        let pattern = "##".parse::<Pattern>().unwrap();
+
+        let matches = find_pattern(&pic, &pattern, true);
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].x, matches[0].y), (2, 1));
+
+        let pattern = "###".parse::<Pattern>().unwrap();
+        assert!(find_pattern(&pic, &pattern, true).is_empty());
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let pic = vec!["###".chars().collect::<Vec<_>>()];
+        let pattern = "##".parse::<Pattern>().unwrap();
+        assert_eq!(pattern.orientations().len(), 2);
+        assert_eq!(find_pattern(&pic, &pattern, true).len(), 2);
+        assert_eq!(find_pattern(&pic, &pattern, false).len(), 1);
+
+        let pattern = "#.\n.#".parse::<Pattern>().unwrap();
+        let pic = vec!["#.#".chars().collect(), ".#.".chars().collect()];
+        let matches = find_pattern(&pic, &pattern, true);
+        assert_eq!(
+            matches
+                .iter()
+                .map(|m| (m.x, m.y, m.orientation))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, 0, Orientation::new(0, false)),
+                (1, 0, Orientation::new(1, false))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let tiles = parse_tiles(INPUT).unwrap();
+        let grid = assemble(&tiles).unwrap();
+        let monster = MONSTER.parse::<Pattern>().unwrap();
+
+        let pic = render(&grid, &tiles, &monster, false);
+        assert_eq!((pic.len(), pic[0].len()), (24, 24));
+        assert_eq!(pic.iter().flatten().filter(|x| **x == 'O').count(), 30);
+
+        let bordered = render(&grid, &tiles, &monster, true);
+        assert_eq!((bordered.len(), bordered[0].len()), (32, 32));
+        assert_eq!(bordered.iter().flatten().filter(|x| **x == 'O').count(), 30);
+        assert!(bordered[10].iter().all(|x| *x == ' '));
+
+        // Without the borders and gutters, both renders are the same picture
+        let stripped = bordered
+            .iter()
+            .enumerate()
+            .filter(|(y, _)| y % 11 != 0 && y % 11 != 9 && y % 11 != 10)
+            .map(|(_, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(x, _)| x % 11 != 0 && x % 11 != 9 && x % 11 != 10)
+                    .map(|(_, ch)| *ch)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(stripped, pic);
+    }
+
+    #[test]
+    fn test_export_image() {
+        let pic = vec!["#.".chars().collect(), "O ".chars().collect()];
+        assert_eq!(export_image(&pic, ImageFormat::Pbm), "P1\n2 2\n1 0\n1 0\n");
+        assert_eq!(
+            export_image(&pic, ImageFormat::Ppm),
+            "P3\n2 2\n255\n127 178 229 30 58 95\n46 204 64 0 0 0\n"
+        );
+
+        assert_eq!(
+            "out/day20.ppm".parse::<ImageFormat>().unwrap(),
+            ImageFormat::Ppm
+        );
+        assert!("day20.png".parse::<ImageFormat>().is_err());
+    }
+
+    #[test]
+    fn test_orientation_group() {
+        let grid = vec!["ab.".chars().collect::<Vec<_>>(), "c.d".chars().collect()];
+        let all = Orientation::all();
+        assert_eq!(all.iter().collect::<HashSet<_>>().len(), 8);
+        assert_eq!(
+            all.iter()
+                .map(|x| x.transform(&grid))
+                .collect::<HashSet<_>>()
+                .len(),
+            8
+        );
+
+        for a in &all {
+            assert_eq!(a.then(&a.inverse()), Orientation::default());
+            assert_eq!(a.inverse().transform(&a.transform(&grid)), grid);
+            for b in &all {
+                assert_eq!(b.transform(&a.transform(&grid)), a.then(b).transform(&grid));
+                for c in &all {
+                    assert_eq!(a.then(b).then(c), a.then(&b.then(c)));
+                }
+            }
+        }
+
+        // A quarter turn clockwise moves the top left corner to the top right
+        let turn = Orientation::new(1, false);
+        assert_eq!(turn.apply(0, 0, 3, 2), (1, 0));
+        assert_eq!(turn.transform(&grid)[0], vec!['c', 'a']);
+        assert_eq!(
+            Orientation::new(0, true).transform(&grid)[0],
+            vec!['.', 'b', 'a']
+        );
+    }
+
+    #[test]
+    fn test_orient_grid() {
+        let tiles = parse_tiles(INPUT).unwrap();
+        let grid = assemble(&tiles).unwrap();
+        let pic = make_picture(&grid, &tiles);
+        for orientation in Orientation::all() {
+            assert_eq!(
+                make_picture(&orient_grid(&grid, &orientation), &tiles),
+                orientation.transform(&pic)
+            );
+        }
     }
 }
@@ -1,105 +1,146 @@
-use std::io::{self, Read};
-use std::error::Error;
 use std::env;
+use std::error::Error;
+use std::io::{self, Read};
 
-use std::collections::{HashMap, HashSet};
+use aoc2020::matching::{self, Solutions};
 use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet};
+
+type Assignment<'a> = BTreeMap<&'a str, &'a str>;
 
 struct Details<'a> {
     constraints: Vec<(Vec<&'a str>, Vec<&'a str>)>,
-    possibilities: HashMap<&'a str, HashSet<&'a str>>,
-    all_foods: HashSet<&'a str>,
-    unused_foods: HashSet<&'a str>,
+    possibilities: BTreeMap<&'a str, BTreeSet<&'a str>>,
+    all_foods: BTreeSet<&'a str>,
+    unused_foods: BTreeSet<&'a str>,
 }
 
-fn extract_details(buffer: &str) -> Result<Details, Box<dyn Error>> {
+fn extract_details(buffer: &str) -> Result<Details<'_>, Box<dyn Error>> {
     let mut constraints: Vec<(Vec<&str>, Vec<&str>)> = Vec::new();
-    let mut possibilities: HashMap<&str, HashSet<&str>> = HashMap::new();
-    let mut all_foods: HashSet<&str> = HashSet::new();
+    let mut possibilities: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let mut all_foods: BTreeSet<&str> = BTreeSet::new();
 
     for line in buffer.lines() {
         let pieces = line.split("(contains").collect::<Vec<_>>();
-        let foods = pieces[0].trim().split(" ").collect::<HashSet<_>>();
-        let allergens = pieces[1][..pieces[1].len() - 1].trim().split(", ").collect::<Vec<_>>();
+        if pieces.len() != 2 {
+            return Err(format!("Missing allergens in '{}'", line).into());
+        }
+        let foods = pieces[0].split_whitespace().collect::<BTreeSet<_>>();
+        let allergens = pieces[1]
+            .trim()
+            .trim_end_matches(')')
+            .split(", ")
+            .collect::<Vec<_>>();
 
-        all_foods.extend(foods.clone());
-        constraints.push((foods.clone().into_iter().collect(), allergens.clone()));
+        all_foods.extend(&foods);
+        constraints.push((foods.iter().copied().collect(), allergens.clone()));
 
         for allergen in allergens {
-            let entry = possibilities.entry(allergen).or_insert(foods.clone());
-            *entry = entry
-                .intersection(&foods)
-                .map(|x| *x)
-                .collect::<HashSet<_>>();
+            let entry = possibilities
+                .entry(allergen)
+                .or_insert_with(|| foods.clone());
+            *entry = entry.intersection(&foods).copied().collect();
         }
     }
 
     let used_foods = possibilities
         .values()
-        .fold(HashSet::new(),
-              |a, x| a.union(x).map(|x| *x).collect());
-
-    let unused_foods =
-        all_foods.difference(&used_foods).copied().collect::<HashSet<_>>();
+        .flatten()
+        .copied()
+        .collect::<BTreeSet<_>>();
+    let unused_foods = all_foods.difference(&used_foods).copied().collect();
 
     Ok(Details {
-        constraints, possibilities, all_foods, unused_foods
+        constraints,
+        possibilities,
+        all_foods,
+        unused_foods,
     })
 }
 
-fn solve1(buffer: &str) -> Result<usize, Box<dyn Error>> {
-    let details = extract_details(buffer)?;
-    Ok(details.constraints
-        .iter()
-        .map(|(f, a)| f.iter().filter(|x| details.unused_foods.contains(**x)).count())
-        .sum())
+impl<'a> Details<'a> {
+    /// The first line (counting from 1) that lists `allergen` but not `food`,
+    /// which is what rules the food out as its source.
+    fn eliminated_by(&self, allergen: &str, food: &str) -> Option<usize> {
+        self.constraints
+            .iter()
+            .position(|(f, a)| a.contains(&allergen) && !f.contains(&food))
+            .map(|x| x + 1)
+    }
 }
 
-fn solve2(buffer: &str) -> Result<String, Box<dyn Error>> {
-    let mut details = extract_details(buffer)?;
-
-    for mut possibility in details.possibilities.iter_mut() {
-        let next = possibility.1.difference(&details.unused_foods)
-            .map(|x| *x)
-            .collect::<HashSet<_>>();
-        possibility.1.clear();
-        possibility.1.extend(next);
+fn unique_assignment<'a>(details: &Details<'a>) -> Result<Assignment<'a>, Box<dyn Error>> {
+    if let Some((allergen, _)) = details.possibilities.iter().find(|(_, x)| x.is_empty()) {
+        return Err(format!("No food appears in every line that lists {}", allergen).into());
     }
 
-    let mut assignments: HashMap<&str, &str> = HashMap::new();
-    loop {
-        let mut new_assignments = HashMap::new();
-        for p in &details.possibilities {
-            if p.1.len() == 1 {
-                new_assignments.insert(*p.0, *(p.1.iter().next().unwrap()));
-            }
+    match matching::Assignment::new(&details.possibilities).solve(10) {
+        Solutions::Unique(assignment) => Ok(assignment),
+        Solutions::None { unmatched } => Err(format!(
+            "Allergens can't each be given a different food: {} left over",
+            unmatched.join(", ")
+        )
+        .into()),
+        Solutions::Many(all) => {
+            let options = all.iter().map(|x| x.values().join(",")).join("; ");
+            Err(format!("More than one assignment fits: {}", options).into())
         }
+    }
+}
 
-        for n in new_assignments.iter() {
-            details.possibilities.remove(*n.0);
-        }
+fn explain(buffer: &str) -> Result<String, Box<dyn Error>> {
+    let details = extract_details(buffer)?;
+    let mut out = String::from("Safe:\n");
+
+    for food in &details.unused_foods {
+        let reasons = details
+            .possibilities
+            .keys()
+            .map(|allergen| match details.eliminated_by(allergen, food) {
+                Some(line) => format!("{} ruled out by line {}", allergen, line),
+                None => format!("{} ruled out by elimination", allergen),
+            })
+            .join(", ");
+        out.push_str(&format!("  {}: {}\n", food, reasons));
+    }
 
-        for n in &new_assignments {
-            for p in details.possibilities.iter_mut() {
-                p.1.remove(*n.1);
+    out.push_str("Allergens:\n");
+    match unique_assignment(&details) {
+        Ok(assignment) => {
+            for (allergen, food) in assignment {
+                out.push_str(&format!("  {}: {}\n", allergen, food));
             }
         }
-
-        assignments.extend(new_assignments);
-
-        if details.possibilities.len() == 0 {
-            break;
-        }
+        Err(e) => out.push_str(&format!("  {}\n", e)),
     }
 
-    Ok(
-        assignments.iter().sorted_by_key(|x| x.0)
-            .map(|x| *x.1)
-            .collect::<Vec<&str>>()
-            .join(",")
-    )
+    let unsafe_foods = details.all_foods.len() - details.unused_foods.len();
+    out.push_str(&format!(
+        "{} foods, {} may contain allergens\n",
+        details.all_foods.len(),
+        unsafe_foods
+    ));
+    Ok(out)
 }
 
+fn solve1(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    let details = extract_details(buffer)?;
+    Ok(details
+        .constraints
+        .iter()
+        .map(|(f, _)| {
+            f.iter()
+                .filter(|x| details.unused_foods.contains(**x))
+                .count()
+        })
+        .sum())
+}
+
+fn solve2(buffer: &str) -> Result<String, Box<dyn Error>> {
+    let details = extract_details(buffer)?;
+    let assignment = unique_assignment(&details)?;
+    Ok(assignment.values().join(","))
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     let now = std::time::Instant::now();
@@ -108,7 +149,9 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() >1 && args[1] == "2" {
+    if args.len() > 1 && args[1] == "explain" {
+        print!("{}", explain(&buffer)?);
+    } else if args.len() > 1 && args[1] == "2" {
         println!("{}", solve2(&buffer)?);
     } else {
         println!("{}", solve1(&buffer)?);
@@ -116,4 +159,67 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     eprintln!("Time: {}ms", now.elapsed().as_millis());
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INPUT: &str = "mxmxvkd kfcds sqjhc nhms (contains dairy, fish)
+trh fvjkl sbzzf mxmxvkd (contains dairy)
+sqjhc fvjkl (contains soy)
+sqjhc mxmxvkd sbzzf (contains fish)";
+
+    #[test]
+    fn test1() {
+        assert_eq!(solve1(INPUT).unwrap(), 5);
+    }
+
+    #[test]
+    fn test2() {
+        assert_eq!(solve2(INPUT).unwrap(), "mxmxvkd,sqjhc,fvjkl");
+    }
+
+    #[test]
+    fn test_explain() {
+        let report = explain(INPUT).unwrap();
+        assert!(report.contains(
+            "  kfcds: dairy ruled out by line 2, fish ruled out by line 4, soy ruled out by line 3\n"
+        ));
+        assert!(report.contains(
+            "  trh: dairy ruled out by line 1, fish ruled out by line 1, soy ruled out by line 3\n"
+        ));
+        assert!(report.contains("Allergens:\n  dairy: mxmxvkd\n  fish: sqjhc\n  soy: fvjkl\n"));
+    }
+
+    #[test]
+    fn test_needs_search() {
+        // Nobody is a singleton until one of the two branches is tried
+        let input = "a b (contains x)\nb c (contains y)\nc a (contains z)\na b c (contains x, y, z)\nd (contains w)";
+        let details = extract_details(input).unwrap();
+        let assignment = matching::Assignment::new(&details.possibilities);
+        assert_eq!(assignment.all(10).len(), 2);
+        assert_eq!(
+            solve2(input).unwrap_err().to_string(),
+            "More than one assignment fits: d,a,b,c; d,b,c,a"
+        );
+
+        let input = "a b (contains x)\nb c (contains y)\nc (contains z)";
+        assert_eq!(solve2(input).unwrap(), "a,b,c");
+    }
+
+    #[test]
+    fn test_contradiction() {
+        let err = solve2("a (contains x)\nb (contains x)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No food appears in every line that lists x"
+        );
+
+        let err = solve2("a b (contains x, y, z)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Allergens can't each be given a different food: z left over"
+        );
+    }
+}
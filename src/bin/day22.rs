@@ -1,19 +1,273 @@
-use std::collections::HashSet;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::env;
 use std::error::Error;
 use std::io::{self, Read};
 
-fn read_deck(piece: &str) -> VecDeque<u64> {
-    piece
+use aoc2020::rng::Rng;
+use itertools::Itertools;
+
+type Deck = VecDeque<u64>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Card {
+    Highest,
+    Lowest,
+}
+
+/// What happens when the winning card is played by more than one player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Ties {
+    Error,
+    /// The tied player listed first takes the round.
+    First,
+    /// Nobody takes the round and its cards leave the game.
+    Discard,
+}
+
+struct Rules {
+    card: Card,
+    recursive: bool,
+    ties: Ties,
+}
+
+impl Rules {
+    fn parse(args: &[String]) -> Result<Rules, Box<dyn Error>> {
+        let mut rules = Rules {
+            card: Card::Highest,
+            recursive: false,
+            ties: Ties::Error,
+        };
+        for arg in args {
+            match arg.as_str() {
+                "highest" => rules.card = Card::Highest,
+                "lowest" => rules.card = Card::Lowest,
+                "recursive" => rules.recursive = true,
+                "ties=error" => rules.ties = Ties::Error,
+                "ties=first" => rules.ties = Ties::First,
+                "ties=discard" => rules.ties = Ties::Discard,
+                _ => return Err(format!("Unknown rule '{}'", arg).into()),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+/// Players are numbered from 0, in the order their decks are listed.
+#[derive(Debug, PartialEq, Eq)]
+struct Round {
+    game: usize,
+    depth: usize,
+    number: usize,
+    cards: Vec<(usize, u64)>,
+    winner: Option<usize>,
+    /// The game that decided this round, if it went to a sub-game.
+    sub_game: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct GameOver {
+    game: usize,
+    depth: usize,
+    winner: usize,
+    rounds: usize,
+    /// Ended because a position came up again rather than by running out.
+    repeated: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Event {
+    Round(Round),
+    GameOver(GameOver),
+}
+
+impl Event {
+    /// One line of JSON, with players numbered from 1 as in the input.
+    fn to_json(&self) -> String {
+        match self {
+            Event::Round(round) => format!(
+                "{{\"type\":\"round\",\"game\":{},\"depth\":{},\"round\":{},\"cards\":[{}],\"winner\":{},\"sub_game\":{}}}",
+                round.game,
+                round.depth,
+                round.number,
+                round
+                    .cards
+                    .iter()
+                    .map(|(p, c)| format!("{{\"player\":{},\"card\":{}}}", p + 1, c))
+                    .join(","),
+                round.winner.map_or(String::from("null"), |w| (w + 1).to_string()),
+                round.sub_game.map_or(String::from("null"), |g| g.to_string())
+            ),
+            Event::GameOver(over) => format!(
+                "{{\"type\":\"game_over\",\"game\":{},\"depth\":{},\"winner\":{},\"rounds\":{},\"repeated\":{}}}",
+                over.game,
+                over.depth,
+                over.winner + 1,
+                over.rounds,
+                over.repeated
+            ),
+        }
+    }
+}
+
+/// How `play` spots a position that came up before in the same game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lookup {
+    /// By `position` key, confirmed by replaying the game when a key repeats.
+    Hashed,
+    /// By whole decks; slower, kept as the reference `bench` checks against.
+    Decks,
+    /// By the decks written out as text, the way this solution started.
+    Strings,
+}
+
+struct Combat<'a> {
+    rules: &'a Rules,
+    lookup: Lookup,
+    games: usize,
+    rounds: usize,
+    history: Option<Vec<Event>>,
+}
+
+const BASE: u64 = 0x0000_0100_0000_01b3;
+const BASE_INVERSE: u64 = inverse(BASE);
+
+/// Multiplicative inverse of an odd number modulo 2^64, by Newton's method.
+const fn inverse(x: u64) -> u64 {
+    let mut y = x;
+    let mut i = 0;
+    while i < 5 {
+        y = y.wrapping_mul(2u64.wrapping_sub(x.wrapping_mul(y)));
+        i += 1;
+    }
+    y
+}
+
+/// Polynomial hash of a deck, top card weighted by 1, kept up to date as
+/// cards are drawn and won so positions compare without walking the decks.
+#[derive(Clone, Copy)]
+struct DeckHash {
+    hash: u64,
+    /// BASE to the power of the deck's length: the weight of the next card.
+    next: u64,
+}
+
+impl DeckHash {
+    fn new(deck: &Deck) -> DeckHash {
+        let mut hash = DeckHash { hash: 0, next: 1 };
+        for &c in deck {
+            hash.push(c);
+        }
+        hash
+    }
+
+    fn push(&mut self, card: u64) {
+        self.hash = self.hash.wrapping_add((card + 1).wrapping_mul(self.next));
+        self.next = self.next.wrapping_mul(BASE);
+    }
+
+    fn pop(&mut self, card: u64) {
+        self.hash = self.hash.wrapping_sub(card + 1).wrapping_mul(BASE_INVERSE);
+        self.next = self.next.wrapping_mul(BASE_INVERSE);
+    }
+}
+
+/// Mixes the deck hashes and lengths into one key. Equal positions always
+/// give equal keys; different ones rarely collide, but can.
+fn position(decks: &[Deck], hashes: &[DeckHash]) -> u64 {
+    decks.iter().zip(hashes).fold(0, |key, (deck, h)| {
+        (key ^ h.hash ^ deck.len() as u64)
+            .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            .rotate_left(31)
+    })
+}
+
+/// Each player with cards left plays their top one.
+fn draw(decks: &mut [Deck]) -> Vec<(usize, u64)> {
+    (0..decks.len())
+        .filter_map(|p| decks[p].pop_front().map(|c| (p, c)))
+        .collect()
+}
+
+/// The cards `winner` takes from a round, their own first.
+fn taken(cards: &[(usize, u64)], winner: usize) -> Vec<u64> {
+    let first = cards.iter().filter(|x| x.0 == winner);
+    let rest = cards.iter().filter(|x| x.0 != winner);
+    first.chain(rest).map(|x| x.1).collect()
+}
+
+/// Positions already played in a game, as the round each came before filed
+/// by `position` key. Only the starting decks and who won each round are
+/// kept, so a position whose key came up before is rebuilt by replaying the
+/// rounds and compared exactly.
+struct Seen {
+    start: Vec<Deck>,
+    rounds: HashMap<u64, Vec<usize>>,
+    winners: Vec<Option<usize>>,
+}
+
+impl Seen {
+    fn new(decks: &[Deck]) -> Seen {
+        Seen {
+            start: decks.to_vec(),
+            rounds: HashMap::new(),
+            winners: vec![],
+        }
+    }
+
+    /// Records `decks`, the position before the next round, under `key`.
+    /// Returns false if the game was in that position before.
+    fn insert(&mut self, key: u64, decks: &[Deck]) -> bool {
+        let (start, winners) = (&self.start, &self.winners);
+        let earlier = self.rounds.entry(key).or_default();
+        if earlier
+            .iter()
+            .any(|&round| replay(start, &winners[..round]) == decks)
+        {
+            return false;
+        }
+        earlier.push(winners.len());
+        true
+    }
+
+    fn record(&mut self, winner: Option<usize>) {
+        self.winners.push(winner);
+    }
+}
+
+/// The decks after playing rounds from `start` with the given winners.
+fn replay(start: &[Deck], winners: &[Option<usize>]) -> Vec<Deck> {
+    let mut decks = start.to_vec();
+    for &winner in winners {
+        let cards = draw(&mut decks);
+        if let Some(w) = winner {
+            decks[w].extend(taken(&cards, w));
+        }
+    }
+    decks
+}
+
+fn read_deck(piece: &str) -> Result<Deck, Box<dyn Error>> {
+    Ok(piece
         .trim()
         .lines()
         .skip(1)
-        .map(|x| x.parse::<u64>().unwrap())
-        .collect::<VecDeque<_>>()
+        .map(|x| x.parse::<u64>())
+        .collect::<Result<Deck, _>>()?)
+}
+
+fn read_decks(buffer: &str) -> Result<Vec<Deck>, Box<dyn Error>> {
+    let decks = buffer
+        .trim()
+        .split("\n\n")
+        .map(read_deck)
+        .collect::<Result<Vec<_>, _>>()?;
+    if decks.len() < 2 {
+        return Err("Need at least two players".into());
+    }
+    Ok(decks)
 }
 
-fn score(deck: &VecDeque<u64>) -> u64 {
+fn score(deck: &Deck) -> u64 {
     deck.iter()
         .rev()
         .enumerate()
@@ -21,104 +275,304 @@ fn score(deck: &VecDeque<u64>) -> u64 {
         .sum()
 }
 
-fn solve1(buffer: &str) -> Result<u64, Box<dyn Error>> {
-    let mut decks = buffer.split("\n\n").map(read_deck).collect::<Vec<_>>();
+impl<'a> Combat<'a> {
+    fn new(rules: &'a Rules, record: bool) -> Combat<'a> {
+        Combat {
+            rules,
+            lookup: Lookup::Hashed,
+            games: 0,
+            rounds: 0,
+            history: if record { Some(vec![]) } else { None },
+        }
+    }
 
-    while decks.iter().all(|x| x.len() > 0) {
-        let p1 = decks[0].pop_front().unwrap();
-        let p2 = decks[1].pop_front().unwrap();
+    fn by_card(&self, cards: &[(usize, u64)]) -> Result<Option<usize>, Box<dyn Error>> {
+        let values = cards.iter().map(|x| x.1);
+        let best = match self.rules.card {
+            Card::Highest => values.max(),
+            Card::Lowest => values.min(),
+        }
+        .unwrap();
 
-        if p1 > p2 {
-            decks[0].push_back(p1);
-            decks[0].push_back(p2);
-        } else if p2 > p1 {
-            decks[1].push_back(p2);
-            decks[1].push_back(p1);
-        } else {
-            unreachable!();
+        let tied = cards.iter().filter(|x| x.1 == best).collect::<Vec<_>>();
+        if tied.len() == 1 {
+            return Ok(Some(tied[0].0));
+        }
+        match self.rules.ties {
+            Ties::Error => Err(format!(
+                "Players {} tied with {}",
+                tied.iter().map(|x| x.0 + 1).join(", "),
+                best
+            )
+            .into()),
+            Ties::First => Ok(Some(tied[0].0)),
+            Ties::Discard => Ok(None),
         }
     }
 
-    let winner = if decks[0].len() > 0 {
-        &decks[0]
-    } else {
-        &decks[1]
-    };
+    /// Plays one game to the end, returning the winner and their deck. A
+    /// position seen before in the same game goes to the first player still in.
+    fn play(
+        &mut self,
+        mut decks: Vec<Deck>,
+        depth: usize,
+    ) -> Result<(usize, Deck), Box<dyn Error>> {
+        self.games += 1;
+        let game = self.games;
+        let mut seen = Seen::new(&decks);
+        let mut seen_decks = HashSet::new();
+        let mut seen_strings = HashSet::new();
+        let mut hashes = decks.iter().map(DeckHash::new).collect::<Vec<_>>();
 
-    Ok(score(winner))
-}
+        for number in 1.. {
+            let active = (0..decks.len())
+                .filter(|p| !decks[*p].is_empty())
+                .collect::<Vec<_>>();
+            match active.len() {
+                0 => return Err(format!("Every deck ran out in game {}", game).into()),
+                1 => {
+                    let over = GameOver {
+                        game,
+                        depth,
+                        winner: active[0],
+                        rounds: number - 1,
+                        repeated: false,
+                    };
+                    return Ok(self.finish(over, &decks));
+                }
+                _ => {}
+            }
 
-fn recursive_combat(deck1: VecDeque<u64>, deck2: VecDeque<u64>) -> (usize, VecDeque<u64>) {
-    let mut rounds = HashSet::new();
-    let mut deck1 = deck1;
-    let mut deck2 = deck2;
+            let repeated = match self.lookup {
+                Lookup::Hashed => !seen.insert(position(&decks, &hashes), &decks),
+                Lookup::Decks => !seen_decks.insert(decks.clone()),
+                Lookup::Strings => {
+                    !seen_strings.insert(decks.iter().map(|x| x.iter().join(",")).join("&"))
+                }
+            };
+            if repeated {
+                let over = GameOver {
+                    game,
+                    depth,
+                    winner: active[0],
+                    rounds: number - 1,
+                    repeated: true,
+                };
+                return Ok(self.finish(over, &decks));
+            }
 
-    while deck1.len() > 0 && deck2.len() > 0 {
-        let p1 = deck1.pop_front().unwrap();
-        let p2 = deck2.pop_front().unwrap();
+            let cards = draw(&mut decks);
+            for &(p, c) in &cards {
+                hashes[p].pop(c);
+            }
+            self.rounds += 1;
 
-        let key = deck1
-            .iter()
-            .map(|x| x.to_string())
-            .collect::<Vec<_>>()
-            .join(",")
-            + "&"
-            + &deck2
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>()
-                .join(",");
-        if rounds.contains(&key) {
-            return (1, deck1);
-        } else {
-            rounds.insert(key);
-        }
-
-        let winner;
-        if p1 <= deck1.len() as u64 && p2 <= deck2.len() as u64 {
-            let results = recursive_combat(
-                (0..(p1 as usize))
-                    .map(|x| deck1[x])
-                    .collect::<VecDeque<_>>(),
-                (0..(p2 as usize))
-                    .map(|x| deck2[x])
-                    .collect::<VecDeque<_>>(),
-            );
-            winner = results.0;
-        } else {
-            if p1 > p2 {
-                winner = 1;
-            } else if p2 > p1 {
-                winner = 2;
+            let recurse =
+                self.rules.recursive && cards.iter().all(|&(p, c)| c as usize <= decks[p].len());
+            let sub_game = if recurse { Some(self.games + 1) } else { None };
+
+            // Logged before any sub-game it starts, with the winner filled in
+            // once known
+            let logged = self.history.as_mut().map(|history| {
+                history.push(Event::Round(Round {
+                    game,
+                    depth,
+                    number,
+                    cards: cards.clone(),
+                    winner: None,
+                    sub_game,
+                }));
+                history.len() - 1
+            });
+
+            let winner = if recurse {
+                let mut sub = vec![Deck::new(); decks.len()];
+                for &(p, c) in &cards {
+                    sub[p] = decks[p].iter().take(c as usize).copied().collect();
+                }
+                Some(self.play(sub, depth + 1)?.0)
             } else {
-                unreachable!();
+                self.by_card(&cards)?
+            };
+
+            if let (Some(history), Some(i)) = (&mut self.history, logged) {
+                if let Event::Round(round) = &mut history[i] {
+                    round.winner = winner;
+                }
+            }
+
+            seen.record(winner);
+            if let Some(w) = winner {
+                for c in taken(&cards, w) {
+                    decks[w].push_back(c);
+                    hashes[w].push(c);
+                }
             }
         }
+        unreachable!()
+    }
 
-        if winner == 1 {
-            deck1.push_back(p1);
-            deck1.push_back(p2);
-        } else if winner == 2 {
-            deck2.push_back(p2);
-            deck2.push_back(p1);
-        } else {
-            unreachable!();
+    fn finish(&mut self, over: GameOver, decks: &[Deck]) -> (usize, Deck) {
+        let result = (over.winner, decks[over.winner].clone());
+        if let Some(history) = &mut self.history {
+            history.push(Event::GameOver(over));
         }
+        result
     }
+}
 
-    if deck1.len() > 0 {
-        (1, deck1)
-    } else {
-        (2, deck2)
+fn show(buffer: &str, rules: &Rules) -> Result<String, Box<dyn Error>> {
+    let decks = read_decks(buffer)?;
+    let mut combat = Combat::new(rules, true);
+    let (winner, deck) = combat.play(decks, 0)?;
+
+    let history = combat.history.unwrap();
+    let main_rounds = history
+        .iter()
+        .filter_map(|x| match x {
+            Event::Round(round) if round.game == 1 => Some(round),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    for round in &main_rounds {
+        let cards = round
+            .cards
+            .iter()
+            .map(|(p, c)| format!("P{} {}", p + 1, c))
+            .join(", ");
+        let result = match (round.winner, round.sub_game) {
+            (Some(w), Some(g)) => format!("P{} (sub-game {})", w + 1, g),
+            (Some(w), None) => format!("P{}", w + 1),
+            (None, _) => String::from("discarded"),
+        };
+        out.push_str(&format!(
+            "Round {}: {} -> {}\n",
+            round.number, cards, result
+        ));
     }
+
+    out.push_str(&format!(
+        "Player {} wins with score {} ({} rounds, {} games)\n",
+        winner + 1,
+        score(&deck),
+        main_rounds.len(),
+        combat.games
+    ));
+    Ok(out)
 }
 
-fn solve2(buffer: &str) -> Result<u64, Box<dyn Error>> {
-    let decks = buffer.split("\n\n").map(read_deck).collect::<Vec<_>>();
-    let (_winner, deck) = recursive_combat(decks[0].clone(), decks[1].clone());
+fn solve(buffer: &str, rules: &Rules) -> Result<u64, Box<dyn Error>> {
+    let decks = read_decks(buffer)?;
+    let (_winner, deck) = Combat::new(rules, false).play(decks, 0)?;
     Ok(score(&deck))
 }
 
+fn log(buffer: &str, rules: &Rules) -> Result<String, Box<dyn Error>> {
+    let mut combat = Combat::new(rules, true);
+    combat.play(read_decks(buffer)?, 0)?;
+    Ok(combat
+        .history
+        .unwrap()
+        .iter()
+        .map(|x| x.to_json() + "\n")
+        .collect())
+}
+
+fn stats(buffer: &str, rules: &Rules) -> Result<String, Box<dyn Error>> {
+    let mut combat = Combat::new(rules, true);
+    let (winner, deck) = combat.play(read_decks(buffer)?, 0)?;
+
+    let games = combat
+        .history
+        .unwrap()
+        .into_iter()
+        .filter_map(|x| match x {
+            Event::GameOver(over) => Some(over),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let rounds = games.iter().map(|x| x.rounds).collect::<Vec<_>>();
+
+    Ok(format!(
+        "Winner: player {} with score {}\n\
+         Games: {} ({} sub-games)\n\
+         Rounds: {} (per game: min {}, mean {:.1}, max {})\n\
+         Max depth: {}\n\
+         Ended by repetition: {} games\n",
+        winner + 1,
+        score(&deck),
+        games.len(),
+        games.len() - 1,
+        combat.rounds,
+        rounds.iter().min().unwrap(),
+        combat.rounds as f64 / games.len() as f64,
+        rounds.iter().max().unwrap(),
+        games.iter().map(|x| x.depth).max().unwrap(),
+        games.iter().filter(|x| x.repeated).count()
+    ))
+}
+
+/// Cards 1 to `count` shuffled and dealt into two decks.
+fn generate_decks(count: usize, rng: &mut Rng) -> Vec<Deck> {
+    let mut cards = (1..=count as u64).collect::<Vec<_>>();
+    for i in (1..cards.len()).rev() {
+        cards.swap(i, rng.below(i + 1));
+    }
+    let second = cards.split_off(count / 2);
+    vec![cards.into(), second.into()]
+}
+
+/// Times recursive combat looking positions up by hash, by whole decks and
+/// by text keys, on the given decks and on `games` generated games of
+/// `count` cards.
+fn bench(buffer: &str, count: usize, games: usize, seed: u64) -> Result<String, Box<dyn Error>> {
+    let rules = Rules::parse(&[String::from("recursive")])?;
+    let mut rng = Rng::new(seed);
+    let mut inputs = vec![(String::from("input"), read_decks(buffer)?)];
+    for i in 1..=games {
+        inputs.push((format!("generated {}", i), generate_decks(count, &mut rng)));
+    }
+
+    let mut out = String::new();
+    for (name, decks) in inputs {
+        let mut results = vec![];
+        for &lookup in &[Lookup::Hashed, Lookup::Decks, Lookup::Strings] {
+            let mut combat = Combat::new(&rules, false);
+            combat.lookup = lookup;
+            let now = std::time::Instant::now();
+            let (winner, deck) = combat.play(decks.clone(), 0)?;
+            let elapsed = now.elapsed().as_secs_f64() * 1000.0;
+            results.push(((winner, score(&deck)), combat.rounds, combat.games, elapsed));
+        }
+        if results.iter().any(|x| x.0 != results[0].0) {
+            return Err(format!("Position lookups disagree on {}", name).into());
+        }
+
+        let (_, rounds, games, hashed) = results[0];
+        out.push_str(&format!(
+            "{}: {} cards, {} rounds in {} games, hashed {:.1}ms, decks {:.1}ms, strings {:.1}ms\n",
+            name,
+            decks.iter().map(|x| x.len()).sum::<usize>(),
+            rounds,
+            games,
+            hashed,
+            results[1].3,
+            results[2].3
+        ));
+    }
+    Ok(out)
+}
+
+fn solve1(buffer: &str) -> Result<u64, Box<dyn Error>> {
+    solve(buffer, &Rules::parse(&[])?)
+}
+
+fn solve2(buffer: &str) -> Result<u64, Box<dyn Error>> {
+    solve(buffer, &Rules::parse(&[String::from("recursive")])?)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let now = std::time::Instant::now();
 
@@ -126,7 +580,18 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "2" {
+    if args.len() > 1 && args[1] == "bench" {
+        let count = args.get(2).map_or(Ok(50), |x| x.parse::<usize>())?;
+        let games = args.get(3).map_or(Ok(5), |x| x.parse::<usize>())?;
+        let seed = args.get(4).map_or(Ok(0), |x| x.parse::<u64>())?;
+        print!("{}", bench(&buffer, count, games, seed)?);
+    } else if args.len() > 1 && args[1] == "log" {
+        print!("{}", log(&buffer, &Rules::parse(&args[2..])?)?);
+    } else if args.len() > 1 && args[1] == "stats" {
+        print!("{}", stats(&buffer, &Rules::parse(&args[2..])?)?);
+    } else if args.len() > 1 && args[1] == "show" {
+        print!("{}", show(&buffer, &Rules::parse(&args[2..])?)?);
+    } else if args.len() > 1 && args[1] == "2" {
         println!("{}", solve2(&buffer)?);
     } else {
         println!("{}", solve1(&buffer)?);
@@ -140,7 +605,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod test {
     use super::*;
 
-    const INPUT: &'static str = "Player 1:
+    const INPUT: &str = "Player 1:
 9
 2
 6
@@ -158,4 +623,152 @@ Player 2:
     fn test2() {
         assert_eq!(solve2(INPUT).unwrap(), 291);
     }
+
+    #[test]
+    fn test1() {
+        assert_eq!(solve1(INPUT).unwrap(), 306);
+    }
+
+    #[test]
+    fn test_repetition() {
+        let input = "Player 1:\n43\n19\n\nPlayer 2:\n2\n29\n14";
+        assert_eq!(solve2(input).unwrap(), 105);
+    }
+
+    #[test]
+    fn test_players() {
+        let input = "Player 1:\n1\n9\n\nPlayer 2:\n5\n2\n\nPlayer 3:\n3\n4";
+        let rules = Rules::parse(&[]).unwrap();
+        let mut combat = Combat::new(&rules, true);
+        let (winner, deck) = combat.play(read_decks(input).unwrap(), 0).unwrap();
+        assert_eq!(winner, 0);
+        assert_eq!(deck, vec![9, 5, 2, 1, 4, 3]);
+
+        let history = combat.history.unwrap();
+        match &history[0] {
+            Event::Round(round) => {
+                assert_eq!(round.cards, vec![(0, 1), (1, 5), (2, 3)]);
+                assert_eq!(round.winner, Some(1));
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+        assert_eq!(history.len(), 6);
+
+        let rules = Rules::parse(&[String::from("lowest")]).unwrap();
+        assert_eq!(
+            solve(input, &rules).unwrap(),
+            score(&vec![1, 2, 5, 9, 3, 4].into())
+        );
+        assert!(show(INPUT, &rules)
+            .unwrap()
+            .starts_with("Round 1: P1 9, P2 5 -> P2\n"));
+    }
+
+    #[test]
+    fn test_ties() {
+        let input = "Player 1:\n4\n1\n\nPlayer 2:\n4\n2";
+        assert_eq!(
+            solve1(input).unwrap_err().to_string(),
+            "Players 1, 2 tied with 4"
+        );
+
+        let rules = Rules::parse(&[String::from("ties=first")]).unwrap();
+        assert_eq!(
+            solve(input, &rules).unwrap(),
+            score(&vec![4, 2, 4, 1].into())
+        );
+
+        let rules = Rules::parse(&[String::from("ties=discard")]).unwrap();
+        assert_eq!(solve(input, &rules).unwrap(), score(&vec![2, 1].into()));
+        let input = "Player 1:\n4\n\nPlayer 2:\n4";
+        assert_eq!(
+            solve(input, &rules).unwrap_err().to_string(),
+            "Every deck ran out in game 1"
+        );
+
+        assert!(Rules::parse(&[String::from("ties=maybe")]).is_err());
+    }
+
+    #[test]
+    fn test_hashing() {
+        assert_eq!(BASE.wrapping_mul(BASE_INVERSE), 1);
+
+        let mut deck: Deck = vec![3, 1, 4, 1, 5].into();
+        let mut hash = DeckHash::new(&deck);
+        for &c in &[9, 2, 6] {
+            let top = deck.pop_front().unwrap();
+            hash.pop(top);
+            deck.push_back(c);
+            hash.push(c);
+            let fresh = DeckHash::new(&deck);
+            assert_eq!((hash.hash, hash.next), (fresh.hash, fresh.next));
+        }
+
+        let empty = vec![Deck::new(), vec![1].into()];
+        let swapped = vec![vec![1].into(), Deck::new()];
+        let hashes = |decks: &Vec<Deck>| decks.iter().map(DeckHash::new).collect::<Vec<_>>();
+        assert_ne!(
+            position(&empty, &hashes(&empty)),
+            position(&swapped, &hashes(&swapped))
+        );
+
+        let output = bench(INPUT, 20, 2, 1).unwrap();
+        assert!(output.starts_with("input: 10 cards, 29 rounds in 5 games"));
+        assert_eq!(output.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_seen_collisions() {
+        let first: Vec<Deck> = vec![vec![1, 2].into(), vec![3].into()];
+        let second: Vec<Deck> = vec![vec![2].into(), vec![3, 1].into()];
+
+        // Both filed under the same key, as if their hashes collided: each
+        // is only a repeat of itself, found by replaying the rounds between
+        let mut seen = Seen::new(&first);
+        assert!(seen.insert(7, &first));
+        seen.record(Some(1));
+        assert_eq!(replay(&first, &seen.winners), second);
+        assert!(seen.insert(7, &second));
+        assert!(!seen.insert(7, &first));
+        assert!(!seen.insert(7, &second));
+        assert_eq!(seen.rounds[&7], vec![0, 1]);
+    }
+
+    #[test]
+    fn test_log() {
+        let rules = Rules::parse(&[String::from("recursive")]).unwrap();
+        let output = log(INPUT, &rules).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 29 + 5);
+        assert_eq!(
+            lines[0],
+            r#"{"type":"round","game":1,"depth":0,"round":1,"cards":[{"player":1,"card":9},{"player":2,"card":5}],"winner":1,"sub_game":null}"#
+        );
+        assert!(lines.contains(
+            &r#"{"type":"game_over","game":2,"depth":1,"winner":2,"rounds":6,"repeated":false}"#
+        ));
+
+        // A round that starts a sub-game comes before that game's events
+        let start = lines
+            .iter()
+            .position(|x| x.contains(r#""sub_game":2"#))
+            .unwrap();
+        assert!(lines[start].contains(r#""game":1"#));
+        assert!(lines[start + 1].contains(r#""game":2,"depth":1,"round":1"#));
+
+        assert_eq!(
+            stats(INPUT, &rules).unwrap(),
+            "Winner: player 2 with score 291
+Games: 5 (4 sub-games)
+Rounds: 29 (per game: min 1, mean 5.8, max 17)
+Max depth: 2
+Ended by repetition: 0 games
+"
+        );
+
+        let input = "Player 1:\n43\n19\n\nPlayer 2:\n2\n29\n14";
+        assert!(stats(input, &rules)
+            .unwrap()
+            .contains("Ended by repetition: 1 games\n"));
+    }
 }
@@ -2,123 +2,255 @@ use std::env;
 use std::error::Error;
 use std::io::{self, Read};
 
-fn solve1(buffer: &str) -> Result<String, Box<dyn Error>> {
-    let cups = solve(buffer, 100, 9)?;
-
-    let mut result = String::from("");
-    let mut point = 1;
-    loop {
-        point = cups[point];
-
-        if point == 1 {
-            break;
-        }
-
-        result += &point.to_string();
-    }
-
-    Ok(result)
+use aoc2020::ring::Ring;
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// Cups labelled 1 to `len`, held in the ring as label - 1.
+#[derive(Clone)]
+struct Game {
+    ring: Ring,
+    /// Sum of `link` over every cup and its successor, kept up to date by
+    /// `step` so positions can be compared without walking the ring.
+    hash: u64,
+    current: usize,
+    pick_up: usize,
+    picked: Vec<usize>,
+    moves: usize,
 }
 
-fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
-    let cups = solve(buffer, 10_000_000, 1_000_000)?;
-    Ok(cups[1] * cups[cups[1]])
+/// The game returns to the position it had after `start` moves every
+/// `period` moves.
+#[derive(Debug, PartialEq, Eq)]
+struct Cycle {
+    start: usize,
+    period: usize,
 }
 
-fn print_debug(cups: &Vec<usize>) {
-    let start = cups[0];
-    let mut point = start;
-
-    let mut max_iter = cups.len();
+/// Hash of one cup being followed by another, mixed with splitmix64's
+/// finaliser so that summing them over a ring scatters well.
+fn link(from: usize, to: usize) -> u64 {
+    let mut z = ((from as u64) << 32 ^ to as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
 
-    loop {
-        print!("{} ", point);
-        point = cups[point];
+fn ring_hash(ring: &Ring) -> u64 {
+    (0..ring.len()).fold(0, |hash, x| hash.wrapping_add(link(x, ring.next(x))))
+}
 
-        max_iter -= 1;
+/// A run of digits is one label per digit; anything with commas or spaces
+/// is a list of labels, which may then have several digits.
+fn parse_labels(buffer: &str) -> Result<Vec<usize>, Box<dyn Error>> {
+    let buffer = buffer.trim();
+    if buffer.contains(|c: char| c == ',' || c.is_whitespace()) {
+        Ok(buffer
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()?)
+    } else {
+        buffer
+            .chars()
+            .map(|x| {
+                x.to_digit(10)
+                    .map(|d| d as usize)
+                    .ok_or_else(|| format!("Bad label '{}'", x).into())
+            })
+            .collect()
+    }
+}
 
-        if point == start {
-            break;
+impl Game {
+    /// Starts with `labels` in order, followed by the labels up to `len`
+    /// that they leave out, in increasing order.
+    fn new(labels: &[usize], len: usize, pick_up: usize) -> Result<Game, Box<dyn Error>> {
+        let len = len.max(labels.len());
+        if len < pick_up + 2 {
+            return Err(
+                format!("Need at least {} cups to pick up {}", pick_up + 2, pick_up).into(),
+            );
         }
 
-        if max_iter == 0 {
-            println!("!");
-            print!("{:?}", cups);
-            break;
+        let mut listed = vec![false; len];
+        for &x in labels {
+            if x == 0 || x > len || listed[x - 1] {
+                return Err(format!("Labels must be distinct and from 1 to {}", len).into());
+            }
+            listed[x - 1] = true;
         }
-    }
 
-    if max_iter > 1 {
-        println!("!");
-        print!("{} {:?}", max_iter, cups);
+        let order = labels
+            .iter()
+            .map(|x| x - 1)
+            .chain((0..len).filter(|x| !listed[*x]))
+            .collect::<Vec<_>>();
+        let ring = Ring::from_order(&order).unwrap();
+
+        Ok(Game {
+            hash: ring_hash(&ring),
+            ring,
+            current: order[0],
+            pick_up,
+            picked: Vec::with_capacity(pick_up),
+            moves: 0,
+        })
     }
 
-    println!();
-}
-
-fn solve(buffer: &str, iter: usize, len: usize) -> Result<Vec<usize>, Box<dyn Error>> {
-    let mut cups: Vec<usize> = vec![0; len + 1];
+    fn step(&mut self) {
+        let len = self.ring.len();
+        self.picked.clear();
+        self.picked.extend(
+            self.ring
+                .iter_from(self.ring.next(self.current))
+                .take(self.pick_up),
+        );
+
+        let mut dest = (self.current + len - 1) % len;
+        while self.picked.contains(&dest) {
+            dest = (dest + len - 1) % len;
+        }
 
-    let input = buffer
-        .trim()
-        .chars()
-        .map(|x| x.to_digit(10).unwrap() as usize)
-        .collect::<Vec<_>>();
-    // println!("{:?}", input);
+        // Only the links out of the current cup, the last one picked up and
+        // the destination change
+        if let (Some(&first), Some(&last)) = (self.picked.first(), self.picked.last()) {
+            let after = self.ring.next(last);
+            let dest_next = self.ring.next(dest);
+            self.hash = self
+                .hash
+                .wrapping_sub(link(self.current, first))
+                .wrapping_sub(link(last, after))
+                .wrapping_sub(link(dest, dest_next))
+                .wrapping_add(link(self.current, after))
+                .wrapping_add(link(last, dest_next))
+                .wrapping_add(link(dest, first));
+        }
 
-    let mut prev = 0;
-    for (i, &x) in input.iter().enumerate() {
-        cups[prev] = x;
-        prev = x;
+        self.ring.move_run(self.current, self.pick_up, dest);
+        self.current = self.ring.next(self.current);
+        self.moves += 1;
     }
 
-    if input.len() < len {
-        cups[prev] = input.len() + 1; // Connect the two
-
-        for i in input.len() + 1..=len {
-            cups[i] = i + 1;
-            prev = i;
+    fn play(&mut self, moves: usize) {
+        for _ in 0..moves {
+            self.step();
         }
     }
 
-    cups[prev] = input[0]; // Make it a circle
-
-    let mut next3 = [0; 3];
-    let mut point = cups[0];
-
-    // print_debug(&cups);
-
-    for _ in 0..iter {
-        let mut t = cups[point];
-        for i in 0..3 {
-            next3[i] = t;
-            t = cups[t];
+    /// Plays up to move `target`, remembering a hash of each position until
+    /// `memory` of them have been stored. When a hash comes up again the
+    /// earlier position is replayed to confirm it, then whole cycles are
+    /// skipped.
+    fn play_to(&mut self, target: usize, memory: usize) -> Option<Cycle> {
+        let origin = self.clone();
+        let mut seen = HashMap::new();
+        while self.moves < target {
+            if seen.len() < memory {
+                let key = self.hash.wrapping_add(link(self.current, self.ring.len()));
+                match seen.get(&key) {
+                    Some(&start) if self.repeats(&origin, start) => {
+                        let period = self.moves - start;
+                        self.moves += (target - self.moves) / period * period;
+                        self.play(target - self.moves);
+                        return Some(Cycle { start, period });
+                    }
+                    // Two positions sharing a hash: keep the earlier one
+                    Some(_) => {}
+                    None => {
+                        seen.insert(key, self.moves);
+                    }
+                }
+            }
+            self.step();
         }
+        None
+    }
 
-        let mut dest = if point - 1 == 0 { len } else { point - 1 };
-        while next3.iter().find(|&&x| x == dest).is_some() {
-            dest -= 1;
-            if dest == 0 {
-                dest = len;
-            }
+    /// Whether playing `origin` on to move `start` gives this position.
+    fn repeats(&self, origin: &Game, start: usize) -> bool {
+        let mut earlier = origin.clone();
+        earlier.play(start - earlier.moves);
+        earlier.current == self.current && earlier.ring == self.ring
+    }
+
+    /// Every cup must be reachable from every other by following the ring.
+    fn check(&self) -> Result<(), String> {
+        match self.ring.cycle_len(self.current) {
+            Some(n) if n == self.ring.len() => Ok(()),
+            Some(n) => Err(format!(
+                "After {} moves cup {} is on a loop of {} out of {} cups",
+                self.moves,
+                self.current + 1,
+                n,
+                self.ring.len()
+            )),
+            None => Err(format!(
+                "After {} moves following cup {} never leads back to it",
+                self.moves,
+                self.current + 1
+            )),
         }
-        // print!("{} | ", dest);
+    }
 
-        // Point -> 0 -> 1 -> 2 -> x ... -> Dest -> y
+    fn snapshot(&self) -> String {
+        format!(
+            "move {}: from 1: {} | from current: {}",
+            self.moves,
+            show_labels(self.ring.iter_from(0)),
+            show_labels(self.ring.iter_from(self.current))
+        )
+    }
 
-        // Point => x
-        cups[point] = cups[next3[2]];
+    /// Labels clockwise from cup 1, not including it.
+    fn after_one(&self) -> impl Iterator<Item = usize> + '_ {
+        self.ring.iter_from(0).skip(1).map(|x| x + 1)
+    }
+}
 
-        // Dest => 0 -> 1 -> 2 => y
-        let y = cups[dest];
-        cups[dest] = next3[0];
-        cups[next3[2]] = y;
+fn show_labels(cups: impl Iterator<Item = usize>) -> String {
+    let mut shown = cups
+        .take(21)
+        .map(|x| (x + 1).to_string())
+        .collect::<Vec<_>>();
+    if shown.len() > 20 {
+        shown[20] = String::from("...");
+    }
+    shown.join(",")
+}
 
-        point = cups[point];
-        // print_debug(&cups);
+fn inspect(
+    buffer: &str,
+    at: &[usize],
+    len: usize,
+    pick_up: usize,
+) -> Result<String, Box<dyn Error>> {
+    let mut game = Game::new(&parse_labels(buffer)?, len, pick_up)?;
+    let mut out = String::new();
+    for &target in at.iter().sorted() {
+        game.play(target - game.moves);
+        game.check()?;
+        out.push_str(&game.snapshot());
+        out.push('\n');
     }
+    Ok(out)
+}
+
+fn solve(buffer: &str, moves: usize, len: usize, pick_up: usize) -> Result<Game, Box<dyn Error>> {
+    let mut game = Game::new(&parse_labels(buffer)?, len, pick_up)?;
+    game.play(moves);
+    Ok(game)
+}
+
+fn solve1(buffer: &str) -> Result<String, Box<dyn Error>> {
+    let game = solve(buffer, 100, 9, 3)?;
+    let result = game.after_one().join("");
+    Ok(result)
+}
 
-    Ok(cups)
+fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    let game = solve(buffer, 10_000_000, 1_000_000, 3)?;
+    Ok(game.after_one().take(2).product())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -128,7 +260,37 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "2" {
+    if args.len() > 2 && args[1] == "play" {
+        // play <moves> [cups [pick_up]] [cycles]
+        let cycles = args[3..].iter().any(|x| x == "cycles");
+        let rest = args[3..]
+            .iter()
+            .filter(|x| *x != "cycles")
+            .collect::<Vec<_>>();
+        let moves = args[2].parse::<usize>()?;
+        let len = rest.first().map_or(Ok(0), |x| x.parse::<usize>())?;
+        let pick_up = rest.get(1).map_or(Ok(3), |x| x.parse::<usize>())?;
+        let mut game = Game::new(&parse_labels(&buffer)?, len, pick_up)?;
+        if !cycles {
+            game.play(moves);
+        } else if let Some(cycle) = game.play_to(moves, 1_000_000) {
+            println!(
+                "Repeats every {} moves from move {}",
+                cycle.period, cycle.start
+            );
+        }
+        game.check()?;
+        println!("{}", game.after_one().take(20).join(","));
+        println!("{}", game.after_one().take(2).product::<usize>());
+    } else if args.len() > 2 && args[1] == "inspect" {
+        let at = args[2]
+            .split(',')
+            .map(|x| x.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let len = args.get(3).map_or(Ok(0), |x| x.parse::<usize>())?;
+        let pick_up = args.get(4).map_or(Ok(3), |x| x.parse::<usize>())?;
+        print!("{}", inspect(&buffer, &at, len, pick_up)?);
+    } else if args.len() > 1 && args[1] == "2" {
         println!("{}", solve2(&buffer)?);
     } else {
         println!("{}", solve1(&buffer)?);
@@ -145,11 +307,79 @@ mod test {
     #[test]
     fn test1() {
         assert_eq!(solve1("389125467").unwrap(), "67384529");
+        let game = solve("389125467", 10, 9, 3).unwrap();
+        assert_eq!(game.after_one().join(""), "92658374");
     }
 
     #[test]
     fn test2() {
-        let results = solve("389125467", 10_000_000, 1_000_000).unwrap();
-        assert_eq!(results[1] * results[results[1]], 149245887792);
+        assert_eq!(solve2("389125467").unwrap(), 149245887792);
+    }
+
+    #[test]
+    fn test_configurable() {
+        assert_eq!(parse_labels("3,8,10, 1\n").unwrap(), vec![3, 8, 10, 1]);
+        assert!(parse_labels("38x").is_err());
+
+        // Same game with the labels written out as a list
+        let game = solve("3,8,9,1,2,5,4,6,7", 100, 0, 3).unwrap();
+        assert_eq!(game.after_one().join(""), "67384529");
+
+        // The missing labels follow in order; 1 is picked up and placed after 2
+        let game = solve("3,1,2,12,10,11", 1, 12, 1).unwrap();
+        assert_eq!(
+            game.after_one().collect::<Vec<_>>(),
+            vec![12, 10, 11, 4, 5, 6, 7, 8, 9, 3, 2]
+        );
+
+        assert!(Game::new(&[1, 2], 2, 1).is_err());
+        assert!(Game::new(&[1, 3, 1], 5, 1).is_err());
+        assert!(Game::new(&[1, 6], 5, 1).is_err());
+    }
+
+    #[test]
+    fn test_inspect() {
+        let output = inspect("389125467", &[10, 0, 1], 0, 3).unwrap();
+        assert_eq!(
+            output,
+            "move 0: from 1: 1,2,5,4,6,7,3,8,9 | from current: 3,8,9,1,2,5,4,6,7
+move 1: from 1: 1,5,4,6,7,3,2,8,9 | from current: 2,8,9,1,5,4,6,7,3
+move 10: from 1: 1,9,2,6,5,8,3,7,4 | from current: 8,3,7,4,1,9,2,6,5
+"
+        );
+
+        let game = Game::new(&[3, 1, 2], 30, 3).unwrap();
+        assert_eq!(
+            game.snapshot(),
+            "move 0: from 1: 1,2,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,... | from current: 3,1,2,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,..."
+        );
+        assert!(game.check().is_ok());
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut game = solve("389125467", 0, 0, 3).unwrap();
+        let cycle = game.play_to(10_000, 1_000_000).unwrap();
+        let slow = solve("389125467", 10_000, 0, 3).unwrap();
+        assert_eq!(game.ring, slow.ring);
+        assert_eq!(game.current, slow.current);
+
+        let mut game = solve("389125467", 0, 0, 3).unwrap();
+        game.play_to(1_000_000_000_000, 1_000_000);
+        let mut slow = solve("389125467", 0, 0, 3).unwrap();
+        slow.play(cycle.start + (1_000_000_000_000 - cycle.start) % cycle.period);
+        assert_eq!(game.ring, slow.ring);
+
+        // The hash kept by each move matches one worked out from scratch
+        assert_eq!(game.hash, ring_hash(&game.ring));
+        let game = solve("3,1,2", 1000, 50, 4).unwrap();
+        assert_eq!(game.hash, ring_hash(&game.ring));
+        assert!(game.repeats(&solve("3,1,2", 0, 50, 4).unwrap(), 1000));
+        assert!(!game.repeats(&solve("3,1,2", 0, 50, 4).unwrap(), 999));
+
+        // Without enough memory it just plays every move
+        let mut game = solve("389125467", 0, 0, 3).unwrap();
+        assert_eq!(game.play_to(100, 10), None);
+        assert_eq!(game.after_one().join(""), "67384529");
     }
 }
@@ -1,126 +1,98 @@
 use std::collections::HashSet;
 use std::env;
 use std::error::Error;
+use std::fs;
 use std::io::{self, Read};
+use std::str::FromStr;
 
-type Floor = HashSet<(i32, i32)>;
+use aoc2020::hex::{Direction, Doubled, Hex};
+use itertools::Itertools;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Dir {
-    E = 0,
-    SE = 1,
-    SW = 2,
+type Floor = HashSet<Hex>;
 
-    W = 3,
-    NW = 4,
-    NE = 5,
+fn identify(steps: &[Direction]) -> Hex {
+    steps
+        .iter()
+        .fold(Hex::ORIGIN, |pos, step| pos.neighbor(*step))
 }
 
-impl Dir {
-    fn axis(&self) -> usize {
-        (*self as usize) % 3
-    }
+fn flip(buffer: &str) -> Result<Floor, Box<dyn Error>> {
+    let mut floor = HashSet::new();
+    for line in buffer.lines().filter(|x| !x.trim().is_empty()) {
+        let pt = identify(&Direction::parse_path(line)?);
 
-    fn step(&self) -> i32 {
-        if (*self as usize) < 3 {
-            1
+        if floor.contains(&pt) {
+            floor.remove(&pt);
         } else {
-            -1
+            floor.insert(pt);
         }
     }
-}
 
-fn tokenize(line: &str) -> Vec<Dir> {
-    let chars = line.chars().collect::<Vec<char>>();
-    let mut result = vec![];
-
-    let mut i = 0;
-    while i < chars.len() {
-        result.push(match chars[i] {
-            'e' => Dir::E,
-            'w' => Dir::W,
-            's' => {
-                i += 1;
-                match chars[i] {
-                    'e' => Dir::SE,
-                    'w' => Dir::SW,
-                    _ => unreachable!(),
-                }
-            }
-            'n' => {
-                i += 1;
-                match chars[i] {
-                    'e' => Dir::NE,
-                    'w' => Dir::NW,
-                    _ => unreachable!(),
-                }
-            }
-            _ => unreachable!(),
-        });
-
-        i += 1;
-    }
-
-    result
+    Ok(floor)
 }
 
-// excluding multiplying by cos30 for y, multiplying by 2 for x to avoid fractions
-fn normalize(pt: [i32; 3]) -> (i32, i32) {
-    (pt[0] * 2 + pt[1] - pt[2], pt[1] + pt[2])
+fn solve1(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    let floor = flip(buffer)?;
+    Ok(floor.len())
 }
 
-fn identify(steps: &Vec<Dir>) -> [i32; 3] {
-    let mut pos = [0; 3];
-    for step in steps {
-        pos[step.axis()] += step.step();
-    }
-    pos
+/// Birth and survival counts in B/S notation, e.g. "B2/S12": a white tile
+/// turns black with 2 black neighbours, a black one stays with 1 or 2.
+#[derive(Debug, PartialEq, Eq)]
+struct Rule {
+    birth: [bool; 7],
+    survival: [bool; 7],
 }
 
-fn flip(buffer: &str) -> HashSet<(i32, i32)> {
-    let mut floor = HashSet::new();
-    for line in buffer.lines() {
-        let steps = tokenize(line);
-        let loc = identify(&steps);
-        let pt = normalize(loc);
+impl FromStr for Rule {
+    type Err = Box<dyn Error>;
 
-        if floor.contains(&pt) {
-            floor.remove(&pt);
-        } else {
-            floor.insert(pt);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let counts = |part: Option<&str>, prefix: char| -> Result<[bool; 7], Self::Err> {
+            let digits = part
+                .and_then(|x| x.trim().strip_prefix(prefix))
+                .ok_or_else(|| format!("Expected a rule like B2/S12, got '{}'", s))?;
+            let mut result = [false; 7];
+            for c in digits.chars() {
+                match c.to_digit(10) {
+                    Some(n) if n <= 6 => result[n as usize] = true,
+                    _ => return Err(format!("Bad neighbour count '{}' in '{}'", c, s).into()),
+                }
+            }
+            Ok(result)
+        };
+
+        let mut parts = s.split('/');
+        let rule = Rule {
+            birth: counts(parts.next(), 'B')?,
+            survival: counts(parts.next(), 'S')?,
+        };
+        if parts.next().is_some() {
+            return Err(format!("Expected a rule like B2/S12, got '{}'", s).into());
         }
+        if rule.birth[0] {
+            return Err("B0 would turn the whole endless floor black".into());
+        }
+        Ok(rule)
     }
-
-    floor
 }
 
-fn solve1(buffer: &str) -> Result<usize, Box<dyn Error>> {
-    let floor = flip(buffer);
-    Ok(floor.len())
-}
+const LOBBY_RULE: &str = "B2/S12";
 
-const OFFSETS: [(i32, i32); 6] = [(-1, -1), (-2, 0), (-1, 1), (1, 1), (2, 0), (1, -1)];
-
-fn black_neighbors((x, y): (i32, i32), floor: &Floor) -> usize {
-    OFFSETS
-        .iter()
-        .filter(|(dx, dy)| floor.contains(&(x + dx, y + dy)))
-        .count()
+fn black_neighbors(tile: Hex, floor: &Floor) -> usize {
+    tile.neighbors().filter(|x| floor.contains(x)).count()
 }
 
-fn day(floor: &Floor) -> Floor {
+fn day(floor: &Floor, rule: &Rule) -> Floor {
     let mut result = HashSet::new();
 
     for black_tile in floor {
-        let bn = black_neighbors(*black_tile, floor);
-        if bn > 0 && bn < 3 {
+        if rule.survival[black_neighbors(*black_tile, floor)] {
             result.insert(*black_tile);
         }
 
-        for (dx, dy) in OFFSETS.iter() {
-            let white_tile = (black_tile.0 + dx, black_tile.1 + dy);
-            let bn = black_neighbors(white_tile, floor);
-            if bn == 2 {
+        for white_tile in black_tile.neighbors().filter(|x| !floor.contains(x)) {
+            if rule.birth[black_neighbors(white_tile, floor)] {
                 result.insert(white_tile);
             }
         }
@@ -129,13 +101,122 @@ fn day(floor: &Floor) -> Floor {
     result
 }
 
-fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
-    let mut floor = flip(buffer);
+fn evolve(floor: &Floor, days: usize, rule: &Rule) -> Floor {
+    (0..days).fold(floor.clone(), |floor, _| day(&floor, rule))
+}
 
-    for _ in 0..100 {
-        floor = day(&floor);
+/// The number of black tiles at the start and after each day, as CSV.
+fn series(floor: &Floor, days: usize, rule: &Rule) -> String {
+    let mut out = String::from("day,black\n");
+    let mut floor = floor.clone();
+    for i in 0..=days {
+        if i > 0 {
+            floor = day(&floor, rule);
+        }
+        out.push_str(&format!("{},{}\n", i, floor.len()));
     }
+    out
+}
 
+/// Every tile in the doubled-coordinate rectangle around the black ones.
+fn area(floor: &Floor) -> Vec<Hex> {
+    let cells = floor.iter().map(|x| x.to_doubled()).collect::<Vec<_>>();
+    let (cols, rows) = match (
+        cells.iter().map(|x| x.col).minmax().into_option(),
+        cells.iter().map(|x| x.row).minmax().into_option(),
+    ) {
+        (Some(cols), Some(rows)) => (cols, rows),
+        _ => return vec![],
+    };
+
+    (rows.0..=rows.1)
+        .flat_map(|row| {
+            (cols.0..=cols.1)
+                .filter(move |col| (col + row) % 2 == 0)
+                .map(move |col| Hex::from_doubled(Doubled { col, row }))
+        })
+        .collect()
+}
+
+/// One line per row, each tile two characters wide and odd rows shifted by
+/// one, so the rows interlock like the floor: '#' black, '.' white.
+fn render_ascii(floor: &Floor) -> String {
+    let tiles = area(floor);
+    let min_col = tiles.iter().map(|x| x.to_doubled().col).min().unwrap_or(0);
+
+    let mut out = String::new();
+    for (_, row) in &tiles.iter().group_by(|x| x.r) {
+        let row = row.collect::<Vec<_>>();
+        let indent = (row[0].to_doubled().col - min_col) as usize;
+        out.push_str(&" ".repeat(indent));
+        let cells = row
+            .iter()
+            .map(|x| if floor.contains(x) { "#" } else { "." })
+            .join(" ");
+        out.push_str(&cells);
+        out.push('\n');
+    }
+    out
+}
+
+fn render_svg(floor: &Floor) -> String {
+    const SIZE: f64 = 10.0;
+    let center = |hex: Hex| {
+        (
+            SIZE * 3f64.sqrt() * (hex.q as f64 + hex.r as f64 / 2.0),
+            SIZE * 1.5 * hex.r as f64,
+        )
+    };
+
+    let tiles = area(floor);
+    let centers = tiles.iter().map(|x| center(*x)).collect::<Vec<_>>();
+    let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+    let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for &(x, y) in &centers {
+        min_x = min_x.min(x - SIZE);
+        min_y = min_y.min(y - SIZE);
+        max_x = max_x.max(x + SIZE);
+        max_y = max_y.max(y + SIZE);
+    }
+    // An empty floor gets an empty picture the size of one tile
+    if centers.is_empty() {
+        (min_x, min_y, max_x, max_y) = (-SIZE, -SIZE, SIZE, SIZE);
+    }
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{:.1} {:.1} {:.1} {:.1}\">\n",
+        min_x,
+        min_y,
+        max_x - min_x,
+        max_y - min_y
+    );
+    for (tile, (x, y)) in tiles.iter().zip(centers) {
+        let points = (0..6)
+            .map(|i| {
+                let angle = (60.0 * i as f64 - 30.0).to_radians();
+                format!(
+                    "{:.1},{:.1}",
+                    x + SIZE * angle.cos(),
+                    y + SIZE * angle.sin()
+                )
+            })
+            .join(" ");
+        let fill = if floor.contains(tile) {
+            "#222222"
+        } else {
+            "#f4f4f4"
+        };
+        out.push_str(&format!(
+            "<polygon points=\"{}\" fill=\"{}\" stroke=\"#999999\"/>\n",
+            points, fill
+        ));
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+fn solve2(buffer: &str) -> Result<usize, Box<dyn Error>> {
+    let floor = evolve(&flip(buffer)?, 100, &LOBBY_RULE.parse()?);
     Ok(floor.len())
 }
 
@@ -146,7 +227,26 @@ fn main() -> Result<(), Box<dyn Error>> {
     io::stdin().read_to_string(&mut buffer)?;
 
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "2" {
+    // show [days [rule]], svg <file> [days [rule]], series [days [rule]]
+    let options = |skip: usize, days: usize| -> Result<(usize, Rule), Box<dyn Error>> {
+        Ok((
+            args.get(skip).map_or(Ok(days), |x| x.parse::<usize>())?,
+            args.get(skip + 1)
+                .map_or(LOBBY_RULE, |x| x)
+                .parse::<Rule>()?,
+        ))
+    };
+
+    if args.len() > 1 && args[1] == "show" {
+        let (days, rule) = options(2, 0)?;
+        print!("{}", render_ascii(&evolve(&flip(&buffer)?, days, &rule)));
+    } else if args.len() > 2 && args[1] == "svg" {
+        let (days, rule) = options(3, 0)?;
+        fs::write(&args[2], render_svg(&evolve(&flip(&buffer)?, days, &rule)))?;
+    } else if args.len() > 1 && args[1] == "series" {
+        let (days, rule) = options(2, 100)?;
+        print!("{}", series(&flip(&buffer)?, days, &rule));
+    } else if args.len() > 1 && args[1] == "2" {
         println!("{}", solve2(&buffer)?);
     } else {
         println!("{}", solve1(&buffer)?);
@@ -160,7 +260,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 mod test {
     use super::*;
 
-    const INPUT: &'static str = "sesenwnenenewseeswwswswwnenewsewsw
+    const INPUT: &str = "sesenwnenenewseeswwswswwnenewsewsw
 neeenesenwnwwswnenewnwwsewnenwseswesw
 seswneswswsenwwnwse
 nwnwneseeswswnenewneswwnewseswneseene
@@ -185,4 +285,66 @@ wseweeenwnesenwwwswnew";
     fn test1b() {
         assert_eq!(solve1(INPUT).unwrap(), 10);
     }
+
+    #[test]
+    fn test2() {
+        assert_eq!(solve2(INPUT).unwrap(), 2208);
+    }
+
+    #[test]
+    fn test_identify() {
+        assert_eq!(
+            identify(&Direction::parse_path("nwwswee").unwrap()),
+            Hex::ORIGIN
+        );
+        assert_eq!(
+            identify(&Direction::parse_path("esew").unwrap()),
+            Hex::new(0, 1)
+        );
+        assert!(flip("esew\nenx").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render_ascii(&flip("").unwrap()), "");
+
+        // East, south-east and south-west of the white reference tile
+        let floor = flip("e\nesew\nsw").unwrap();
+        assert_eq!(render_ascii(&floor), " . #\n# #\n");
+
+        let svg = render_svg(&floor);
+        assert!(svg.starts_with("<svg "));
+        assert_eq!(svg.matches("<polygon").count(), 4);
+        assert_eq!(svg.matches("#222222").count(), 3);
+
+        // The picture is fitted to the tiles, wherever they are
+        let far = flip("eeeeeeeeeeeeee").unwrap();
+        assert!(render_svg(&far).contains("viewBox=\"232.5 -10.0 20.0 20.0\""));
+        assert!(render_svg(&flip("").unwrap()).contains("viewBox=\"-10.0 -10.0 20.0 20.0\""));
+
+        let after = evolve(&floor, 1, &LOBBY_RULE.parse().unwrap());
+        assert_eq!(render_ascii(&after), " . #\n# # #\n # .\n");
+    }
+
+    #[test]
+    fn test_rules() {
+        let rule = LOBBY_RULE.parse::<Rule>().unwrap();
+        assert!(rule.birth[2] && !rule.birth[1] && !rule.birth[3]);
+        assert!(rule.survival[1] && rule.survival[2] && !rule.survival[0]);
+
+        assert!("B2".parse::<Rule>().is_err());
+        assert!("B2/S17".parse::<Rule>().is_err());
+        assert!("S12/B2".parse::<Rule>().is_err());
+        assert!("B02/S12".parse::<Rule>().is_err());
+
+        let floor = flip(INPUT).unwrap();
+        let output = series(&floor, 10, &rule);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[..4], ["day,black", "0,10", "1,15", "2,12"]);
+        assert_eq!(lines[11], "10,37");
+
+        // Nothing is born and everything survives
+        let frozen = "B/S0123456".parse::<Rule>().unwrap();
+        assert_eq!(evolve(&floor, 5, &frozen), floor);
+    }
 }
@@ -0,0 +1,326 @@
+//! Coordinates on a grid of pointy-topped hexagons, whose rows run east to
+//! west. `Hex` is the axial form everything else converts through; rows
+//! count downwards, so south-east is `r + 1`.
+
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hex {
+    pub q: i32,
+    pub r: i32,
+}
+
+/// Three axes summing to zero: `x` is `q`, `z` is `r`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cube {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// Columns counted in half hexes, so neighbours in a row are two apart and
+/// `col + row` is always even.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Doubled {
+    pub col: i32,
+    pub row: i32,
+}
+
+/// Rows and columns of a rectangular layout, odd rows shoved half a hex
+/// to the right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Offset {
+    pub col: i32,
+    pub row: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    E,
+    SE,
+    SW,
+    W,
+    NW,
+    NE,
+}
+
+impl Direction {
+    /// Clockwise from east.
+    pub const ALL: [Direction; 6] = [
+        Direction::E,
+        Direction::SE,
+        Direction::SW,
+        Direction::W,
+        Direction::NW,
+        Direction::NE,
+    ];
+
+    pub fn offset(self) -> Hex {
+        match self {
+            Direction::E => Hex::new(1, 0),
+            Direction::SE => Hex::new(0, 1),
+            Direction::SW => Hex::new(-1, 1),
+            Direction::W => Hex::new(-1, 0),
+            Direction::NW => Hex::new(0, -1),
+            Direction::NE => Hex::new(1, -1),
+        }
+    }
+
+    /// Splits run-together directions such as "esenee" into steps.
+    pub fn parse_path(line: &str) -> Result<Vec<Direction>, String> {
+        let mut result = vec![];
+        let mut chars = line.trim().chars();
+        while let Some(c) = chars.next() {
+            result.push(match c {
+                'e' => Direction::E,
+                'w' => Direction::W,
+                's' | 'n' => match (c, chars.next()) {
+                    ('s', Some('e')) => Direction::SE,
+                    ('s', Some('w')) => Direction::SW,
+                    ('n', Some('e')) => Direction::NE,
+                    ('n', Some('w')) => Direction::NW,
+                    (_, x) => {
+                        return Err(format!(
+                            "Bad direction '{}{}' in '{}'",
+                            c,
+                            x.unwrap_or(' '),
+                            line
+                        ))
+                    }
+                },
+                _ => return Err(format!("Bad direction '{}' in '{}'", c, line)),
+            });
+        }
+        Ok(result)
+    }
+}
+
+impl Hex {
+    pub const ORIGIN: Hex = Hex { q: 0, r: 0 };
+
+    pub fn new(q: i32, r: i32) -> Hex {
+        Hex { q, r }
+    }
+
+    pub fn to_cube(self) -> Cube {
+        Cube {
+            x: self.q,
+            y: -self.q - self.r,
+            z: self.r,
+        }
+    }
+
+    pub fn from_cube(cube: Cube) -> Hex {
+        Hex::new(cube.x, cube.z)
+    }
+
+    pub fn to_doubled(self) -> Doubled {
+        Doubled {
+            col: 2 * self.q + self.r,
+            row: self.r,
+        }
+    }
+
+    pub fn from_doubled(doubled: Doubled) -> Hex {
+        Hex::new((doubled.col - doubled.row).div_euclid(2), doubled.row)
+    }
+
+    pub fn to_offset(self) -> Offset {
+        Offset {
+            col: self.q + (self.r - (self.r & 1)) / 2,
+            row: self.r,
+        }
+    }
+
+    pub fn from_offset(offset: Offset) -> Hex {
+        Hex::new(offset.col - (offset.row - (offset.row & 1)) / 2, offset.row)
+    }
+
+    pub fn neighbor(self, dir: Direction) -> Hex {
+        self + dir.offset()
+    }
+
+    pub fn neighbors(self) -> impl Iterator<Item = Hex> {
+        Direction::ALL.iter().map(move |d| self.neighbor(*d))
+    }
+
+    pub fn distance(self, other: Hex) -> i32 {
+        let d = (other - self).to_cube();
+        (d.x.abs() + d.y.abs() + d.z.abs()) / 2
+    }
+
+    /// The `6 * radius` hexes at exactly `radius` steps, clockwise from the
+    /// westernmost.
+    pub fn ring(self, radius: i32) -> Vec<Hex> {
+        if radius <= 0 {
+            return vec![self];
+        }
+
+        let mut hex = self + Direction::W.offset() * radius;
+        let mut result = vec![];
+        for &dir in &[
+            Direction::NE,
+            Direction::E,
+            Direction::SE,
+            Direction::SW,
+            Direction::W,
+            Direction::NW,
+        ] {
+            for _ in 0..radius {
+                result.push(hex);
+                hex = hex.neighbor(dir);
+            }
+        }
+        result
+    }
+
+    /// The hexes a straight line from the centre of `self` to the centre of
+    /// `other` passes through, both ends included.
+    pub fn line_to(self, other: Hex) -> Vec<Hex> {
+        let n = self.distance(other);
+        let (a, b) = (self.to_cube(), other.to_cube());
+        // Nudged so that lines along hex edges fall consistently to one side
+        let lerp = |a: i32, b: i32, t: f64, e: f64| a as f64 + (b - a) as f64 * t + e;
+
+        (0..=n)
+            .map(|i| {
+                let t = if n == 0 { 0.0 } else { i as f64 / n as f64 };
+                round_cube(
+                    lerp(a.x, b.x, t, 1e-6),
+                    lerp(a.y, b.y, t, 2e-6),
+                    lerp(a.z, b.z, t, -3e-6),
+                )
+            })
+            .collect()
+    }
+
+    /// Turns 60° clockwise around the origin `steps` times; negative steps
+    /// turn anticlockwise.
+    pub fn rotate(self, steps: i32) -> Hex {
+        (0..steps.rem_euclid(6)).fold(self, |h, _| Hex::new(-h.r, h.q + h.r))
+    }
+
+    pub fn rotate_about(self, center: Hex, steps: i32) -> Hex {
+        center + (self - center).rotate(steps)
+    }
+}
+
+fn round_cube(x: f64, y: f64, z: f64) -> Hex {
+    let (mut rx, ry, mut rz) = (x.round(), y.round(), z.round());
+    let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+    if dx > dy && dx > dz {
+        rx = -ry - rz;
+    } else if dz > dy {
+        rz = -rx - ry;
+    }
+    Hex::new(rx as i32, rz as i32)
+}
+
+impl Add for Hex {
+    type Output = Hex;
+
+    fn add(self, other: Hex) -> Hex {
+        Hex::new(self.q + other.q, self.r + other.r)
+    }
+}
+
+impl Sub for Hex {
+    type Output = Hex;
+
+    fn sub(self, other: Hex) -> Hex {
+        Hex::new(self.q - other.q, self.r - other.r)
+    }
+}
+
+impl Mul<i32> for Hex {
+    type Output = Hex;
+
+    fn mul(self, k: i32) -> Hex {
+        Hex::new(self.q * k, self.r * k)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        for q in -4..=4 {
+            for r in -4..=4 {
+                let hex = Hex::new(q, r);
+                let cube = hex.to_cube();
+                assert_eq!(cube.x + cube.y + cube.z, 0);
+                assert_eq!(Hex::from_cube(cube), hex);
+                assert_eq!(Hex::from_doubled(hex.to_doubled()), hex);
+                assert_eq!(Hex::from_offset(hex.to_offset()), hex);
+                assert_eq!((hex.to_doubled().col + r).rem_euclid(2), 0);
+            }
+        }
+
+        let se = Hex::ORIGIN.neighbor(Direction::SE);
+        assert_eq!(se.to_doubled(), Doubled { col: 1, row: 1 });
+        assert_eq!(se.to_offset(), Offset { col: 0, row: 1 });
+        let row = Hex::new(-1, 2).to_offset();
+        assert_eq!(row, Offset { col: 0, row: 2 });
+    }
+
+    #[test]
+    fn test_paths() {
+        let path = Direction::parse_path("nwwswee").unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!(
+            path.iter().fold(Hex::ORIGIN, |h, d| h.neighbor(*d)),
+            Hex::ORIGIN
+        );
+        assert!(Direction::parse_path("esx").is_err());
+        assert!(Direction::parse_path("en").is_err());
+
+        for dir in Direction::ALL.iter() {
+            assert_eq!(dir.offset().distance(Hex::ORIGIN), 1);
+        }
+        assert_eq!(Hex::new(3, -1).distance(Hex::new(-2, 2)), 5);
+    }
+
+    #[test]
+    fn test_rings_and_lines() {
+        assert_eq!(Hex::ORIGIN.ring(0), vec![Hex::ORIGIN]);
+        let center = Hex::new(2, -1);
+        for radius in 1..5 {
+            let ring = center.ring(radius);
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert!(ring.iter().all(|h| h.distance(center) == radius));
+            assert_eq!(
+                ring.iter().collect::<std::collections::HashSet<_>>().len(),
+                ring.len()
+            );
+        }
+
+        let line = Hex::new(-2, 0).line_to(Hex::new(2, 1));
+        assert_eq!(line.len(), 6);
+        assert!(line.windows(2).all(|w| w[0].distance(w[1]) == 1));
+        assert_eq!(line[0], Hex::new(-2, 0));
+        assert_eq!(line[5], Hex::new(2, 1));
+        assert_eq!(Hex::ORIGIN.line_to(Hex::ORIGIN), vec![Hex::ORIGIN]);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let east = Direction::E.offset();
+        assert_eq!(east.rotate(1), Direction::SE.offset());
+        assert_eq!(east.rotate(-1), Direction::NE.offset());
+        for (i, dir) in Direction::ALL.iter().enumerate() {
+            assert_eq!(east.rotate(i as i32), dir.offset());
+        }
+
+        let hex = Hex::new(3, -2);
+        assert_eq!(hex.rotate(6), hex);
+        assert_eq!(hex.rotate(2).rotate(-2), hex);
+        let center = Hex::new(1, 1);
+        assert_eq!(
+            hex.rotate_about(center, 3).distance(center),
+            hex.distance(center)
+        );
+        assert_eq!(center.rotate_about(center, 1), center);
+    }
+}
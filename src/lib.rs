@@ -0,0 +1,4 @@
+pub mod hex;
+pub mod matching;
+pub mod ring;
+pub mod rng;
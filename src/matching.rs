@@ -0,0 +1,286 @@
+//! Giving every key its own value out of a set of candidates, i.e. matchings
+//! in a bipartite graph: ticket fields to columns, allergens to ingredients.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Solutions<K, V> {
+    /// No way to give every key a value; `unmatched` are the keys left out
+    /// of a largest possible matching.
+    None {
+        unmatched: Vec<K>,
+    },
+    Unique(BTreeMap<K, V>),
+    /// Every valid assignment, up to the requested limit.
+    Many(Vec<BTreeMap<K, V>>),
+}
+
+/// Bipartite graph from keys to the values they may take, stored by index.
+pub struct Assignment<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    edges: Vec<Vec<usize>>,
+}
+
+impl<K: Ord + Clone, V: Ord + Clone> Assignment<K, V> {
+    pub fn new(candidates: &BTreeMap<K, BTreeSet<V>>) -> Assignment<K, V> {
+        let values = candidates
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let edges = candidates
+            .values()
+            .map(|x| x.iter().map(|v| values.binary_search(v).unwrap()).collect())
+            .collect();
+
+        Assignment {
+            keys: candidates.keys().cloned().collect(),
+            values,
+            edges,
+        }
+    }
+
+    /// A largest matching, found with Hopcroft–Karp.
+    pub fn maximum(&self) -> BTreeMap<K, V> {
+        self.to_map(&hopcroft_karp(&self.edges, self.values.len()))
+    }
+
+    /// Whether some other assignment gives every key a value too. Starting
+    /// from a complete one, that needs a key that could switch to a free
+    /// value, or a cycle of keys that could each take the next one's value.
+    fn is_unique(&self, matching: &[Option<usize>]) -> bool {
+        let mut owner = vec![None; self.values.len()];
+        for (k, v) in matching.iter().enumerate() {
+            owner[v.unwrap()] = Some(k);
+        }
+
+        // Key k points at the key whose value it could take instead
+        let mut next = vec![vec![]; self.keys.len()];
+        for (k, values) in self.edges.iter().enumerate() {
+            for &v in values {
+                match owner[v] {
+                    None => return false,
+                    Some(o) if o != k => next[k].push(o),
+                    Some(_) => {}
+                }
+            }
+        }
+
+        !has_cycle(&next)
+    }
+
+    /// Every complete assignment, stopping after `limit`.
+    pub fn all(&self, limit: usize) -> Vec<BTreeMap<K, V>> {
+        let mut results = vec![];
+        self.extend(self.edges.clone(), limit, &mut results);
+        results
+    }
+
+    /// Pins down one undecided key at a time, only following choices that
+    /// still leave a complete assignment.
+    fn extend(&self, edges: Vec<Vec<usize>>, limit: usize, results: &mut Vec<BTreeMap<K, V>>) {
+        let matching = hopcroft_karp(&edges, self.values.len());
+        if matching.iter().any(|x| x.is_none()) {
+            return;
+        }
+
+        let open = (0..edges.len()).find(|k| edges[*k].len() > 1);
+        let k = match open {
+            Some(k) => k,
+            None => {
+                results.push(self.to_map(&matching));
+                return;
+            }
+        };
+
+        for &v in &edges[k] {
+            if results.len() >= limit {
+                return;
+            }
+            let mut pinned = edges
+                .iter()
+                .map(|x| x.iter().copied().filter(|w| *w != v).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            pinned[k] = vec![v];
+            self.extend(pinned, limit, results);
+        }
+    }
+
+    pub fn solve(&self, limit: usize) -> Solutions<K, V> {
+        let matching = hopcroft_karp(&self.edges, self.values.len());
+        if matching.iter().any(|x| x.is_none()) {
+            let unmatched = matching
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| v.is_none())
+                .map(|(k, _)| self.keys[k].clone())
+                .collect();
+            return Solutions::None { unmatched };
+        }
+
+        if self.is_unique(&matching) {
+            Solutions::Unique(self.to_map(&matching))
+        } else {
+            Solutions::Many(self.all(limit))
+        }
+    }
+
+    fn to_map(&self, matching: &[Option<usize>]) -> BTreeMap<K, V> {
+        matching
+            .iter()
+            .enumerate()
+            .filter_map(|(k, v)| v.map(|v| (self.keys[k].clone(), self.values[v].clone())))
+            .collect()
+    }
+}
+
+/// For each left vertex, the right vertex it's matched with.
+fn hopcroft_karp(edges: &[Vec<usize>], right_count: usize) -> Vec<Option<usize>> {
+    let mut left_match: Vec<Option<usize>> = vec![None; edges.len()];
+    let mut right_match: Vec<Option<usize>> = vec![None; right_count];
+
+    loop {
+        // Layer the free left vertices and everything alternating paths reach
+        let mut dist = vec![usize::MAX; edges.len()];
+        let mut queue = VecDeque::new();
+        for (u, m) in left_match.iter().enumerate() {
+            if m.is_none() {
+                dist[u] = 0;
+                queue.push_back(u);
+            }
+        }
+
+        let mut found = false;
+        while let Some(u) = queue.pop_front() {
+            for &v in &edges[u] {
+                match right_match[v] {
+                    None => found = true,
+                    Some(w) if dist[w] == usize::MAX => {
+                        dist[w] = dist[u] + 1;
+                        queue.push_back(w);
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        if !found {
+            return left_match;
+        }
+
+        for u in 0..edges.len() {
+            if left_match[u].is_none() {
+                augment(u, edges, &mut dist, &mut left_match, &mut right_match);
+            }
+        }
+    }
+}
+
+fn augment(
+    u: usize,
+    edges: &[Vec<usize>],
+    dist: &mut [usize],
+    left_match: &mut [Option<usize>],
+    right_match: &mut [Option<usize>],
+) -> bool {
+    for &v in &edges[u] {
+        let next = match right_match[v] {
+            None => true,
+            Some(w) => dist[w] == dist[u] + 1 && augment(w, edges, dist, left_match, right_match),
+        };
+        if next {
+            left_match[u] = Some(v);
+            right_match[v] = Some(u);
+            return true;
+        }
+    }
+
+    // Dead end: don't come back this phase
+    dist[u] = usize::MAX;
+    false
+}
+
+fn has_cycle(next: &[Vec<usize>]) -> bool {
+    // 0: unvisited, 1: on the current path, 2: done
+    fn visit(u: usize, next: &[Vec<usize>], state: &mut [u8]) -> bool {
+        state[u] = 1;
+        for &w in &next[u] {
+            if state[w] == 1 || (state[w] == 0 && visit(w, next, state)) {
+                return true;
+            }
+        }
+        state[u] = 2;
+        false
+    }
+
+    let mut state = vec![0; next.len()];
+    (0..next.len()).any(|u| state[u] == 0 && visit(u, next, &mut state))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn candidates(pairs: &[(&'static str, &[u32])]) -> BTreeMap<&'static str, BTreeSet<u32>> {
+        pairs
+            .iter()
+            .map(|(k, vs)| (*k, vs.iter().copied().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_unique() {
+        let assignment = Assignment::new(&candidates(&[
+            ("a", &[1, 2, 3]),
+            ("b", &[2]),
+            ("c", &[2, 3]),
+        ]));
+        let expected = vec![("a", 1), ("b", 2), ("c", 3)].into_iter().collect();
+        assert_eq!(assignment.solve(10), Solutions::Unique(expected));
+    }
+
+    #[test]
+    fn test_many() {
+        // No singleton to start eliminating from
+        let assignment = Assignment::new(&candidates(&[
+            ("a", &[1, 2]),
+            ("b", &[2, 3]),
+            ("c", &[3, 1]),
+        ]));
+        match assignment.solve(10) {
+            Solutions::Many(all) => {
+                assert_eq!(all.len(), 2);
+                assert_eq!(all[0][&"a"], 1);
+                assert_eq!(all[1][&"a"], 2);
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+
+        // A spare value is enough to make it ambiguous
+        let assignment = Assignment::new(&candidates(&[("a", &[1]), ("b", &[2, 3])]));
+        assert_eq!(assignment.all(10).len(), 2);
+        assert!(matches!(assignment.solve(10), Solutions::Many(_)));
+
+        let assignment = Assignment::new(&candidates(&[
+            ("a", &[1, 2, 3]),
+            ("b", &[1, 2, 3]),
+            ("c", &[1, 2, 3]),
+        ]));
+        assert_eq!(assignment.all(100).len(), 6);
+        assert_eq!(assignment.all(4).len(), 4);
+    }
+
+    #[test]
+    fn test_none() {
+        let assignment = Assignment::new(&candidates(&[("a", &[1]), ("b", &[1]), ("c", &[1, 2])]));
+        assert_eq!(assignment.maximum().len(), 2);
+        match assignment.solve(10) {
+            Solutions::None { unmatched } => assert_eq!(unmatched.len(), 1),
+            x => panic!("unexpected {:?}", x),
+        }
+        assert!(assignment.all(10).is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+//! A circle of the values `0..len` stored as a successor array, so that
+//! following and relinking runs of nodes is constant time per node.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ring {
+    next: Vec<usize>,
+}
+
+impl Ring {
+    /// The ring visiting `order` from start to end and back to the start.
+    /// `None` unless `order` holds each of `0..order.len()` exactly once.
+    pub fn from_order(order: &[usize]) -> Option<Ring> {
+        let mut next = vec![usize::MAX; order.len()];
+        for (i, &x) in order.iter().enumerate() {
+            if x >= order.len() || next[x] != usize::MAX {
+                return None;
+            }
+            next[x] = order[(i + 1) % order.len()];
+        }
+        Some(Ring { next })
+    }
+
+    pub fn len(&self) -> usize {
+        self.next.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next.is_empty()
+    }
+
+    pub fn next(&self, x: usize) -> usize {
+        self.next[x]
+    }
+
+    /// Every node once, starting from `start`.
+    pub fn iter_from(&self, start: usize) -> Iter<'_> {
+        Iter {
+            ring: self,
+            start,
+            current: Some(start),
+        }
+    }
+
+    /// Unlinks the `count` nodes following `after` and splices them back in
+    /// between `dest` and its successor. `dest` must not be one of them.
+    pub fn move_run(&mut self, after: usize, count: usize, dest: usize) {
+        if count == 0 {
+            return;
+        }
+        let first = self.next[after];
+        let last = (1..count).fold(first, |x, _| self.next[x]);
+
+        self.next[after] = self.next[last];
+        self.next[last] = self.next[dest];
+        self.next[dest] = first;
+    }
+
+    /// How many steps following `start` takes to get back to it, which is
+    /// `len()` for a well-formed ring; `None` if it never does.
+    pub fn cycle_len(&self, start: usize) -> Option<usize> {
+        let mut x = start;
+        for steps in 1..=self.next.len() {
+            x = *self.next.get(x)?;
+            if x == start {
+                return Some(steps);
+            }
+        }
+        None
+    }
+}
+
+pub struct Iter<'a> {
+    ring: &'a Ring,
+    start: usize,
+    current: Option<usize>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let x = self.current?;
+        let next = self.ring.next[x];
+        self.current = if next == self.start { None } else { Some(next) };
+        Some(x)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_order() {
+        let ring = Ring::from_order(&[2, 0, 3, 1]).unwrap();
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring.next(1), 2);
+        assert_eq!(ring.iter_from(0).collect::<Vec<_>>(), vec![0, 3, 1, 2]);
+
+        assert_eq!(ring.cycle_len(3), Some(4));
+
+        assert!(Ring::from_order(&[0, 0, 1]).is_none());
+        assert!(Ring::from_order(&[0, 3]).is_none());
+    }
+
+    #[test]
+    fn test_move_run() {
+        let mut ring = Ring::from_order(&[0, 1, 2, 3, 4, 5]).unwrap();
+        ring.move_run(0, 2, 4);
+        assert_eq!(ring.iter_from(0).collect::<Vec<_>>(), vec![0, 3, 4, 1, 2, 5]);
+
+        // Wrapping around past the start
+        ring.move_run(2, 2, 4);
+        assert_eq!(ring.iter_from(1).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 0]);
+
+        ring.move_run(0, 0, 1);
+        assert_eq!(ring.iter_from(1).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 0]);
+    }
+
+    #[test]
+    fn test_cycle_len() {
+        let ring = Ring {
+            next: vec![1, 0, 2],
+        };
+        assert_eq!(ring.cycle_len(0), Some(2));
+        assert_eq!(ring.cycle_len(2), Some(1));
+
+        let ring = Ring {
+            next: vec![1, 2, 1, 7],
+        };
+        assert_eq!(ring.cycle_len(0), None);
+        assert_eq!(ring.cycle_len(3), None);
+    }
+}
@@ -0,0 +1,50 @@
+//! xorshift64*: a small seedable generator, plenty random for fuzzing and
+//! generating puzzles, and reproducible across runs.
+
+pub struct Rng(u64);
+
+impl Rng {
+    /// Xorshift never leaves an all-zero state, so the one seed that would
+    /// produce it is swapped for another constant.
+    pub fn new(seed: u64) -> Rng {
+        match seed ^ 0x9e37_79b9_7f4a_7c15 {
+            0 => Rng(0x2545_f491_4f6c_dd1d),
+            state => Rng(state),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A value in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seeds() {
+        for seed in &[0, 0x9e37_79b9_7f4a_7c15] {
+            let mut rng = Rng::new(*seed);
+            let values = (0..4).map(|_| rng.next_u64()).collect::<Vec<_>>();
+            assert!(values.iter().all(|x| *x != 0));
+            assert_ne!(values[0], values[1]);
+        }
+    }
+
+    #[test]
+    fn test_below() {
+        let mut rng = Rng::new(2020);
+        let values = (0..1000).map(|_| rng.below(6)).collect::<Vec<_>>();
+        assert!(values.iter().all(|x| *x < 6));
+        assert!((0..6).all(|x| values.contains(&x)));
+    }
+}